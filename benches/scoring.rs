@@ -0,0 +1,215 @@
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use fuzzyq::algorithms;
+use fuzzyq::structs::{PreparedCandidate, ScoringConfig};
+
+const WORDS: &str = include_str!("../words.txt");
+
+// 50k options of two words each
+fn candidates() -> Vec<String> {
+    let words: Vec<&str> = WORDS.lines().collect();
+    (0..50_000)
+        .map(|i| format!("{} {}", words[i % words.len()], words[i / words.len()]))
+        .collect()
+}
+
+// a query far longer than the words, which the bound rules out before the DP
+fn edit_distance(c: &mut Criterion) {
+    let query = "internationalization";
+    let mut group = c.benchmark_group("edit_distance");
+    group.bench_function("bounded", |b| {
+        b.iter(|| {
+            WORDS
+                .lines()
+                .filter_map(|word| algorithms::levenshtein_bounded(black_box(query), word, 2))
+                .count()
+        })
+    });
+    group.bench_function("full", |b| {
+        b.iter(|| {
+            WORDS
+                .lines()
+                .filter_map(|word| {
+                    algorithms::levenshtein_bounded(black_box(query), word, usize::MAX)
+                })
+                .count()
+        })
+    });
+    group.finish();
+}
+
+// the candidate features computed on every keystroke against once when the options are loaded
+fn prepared(c: &mut Criterion) {
+    let config = ScoringConfig::default();
+    let candidates = candidates();
+    let prepared: Vec<PreparedCandidate> = candidates
+        .iter()
+        .map(|c| PreparedCandidate::new(c))
+        .collect();
+    let mut group = c.benchmark_group("prepared");
+    group.sample_size(20);
+    group.bench_function("on_the_fly", |b| {
+        b.iter(|| {
+            candidates
+                .iter()
+                .filter_map(|c| algorithms::fuzzy_match(black_box("ther"), c, &config))
+                .count()
+        })
+    });
+    group.bench_function("once", |b| {
+        b.iter(|| {
+            candidates
+                .iter()
+                .zip(&prepared)
+                .filter_map(|(c, prepared)| {
+                    algorithms::fuzzy_match_prepared(black_box("ther"), c, prepared, &config)
+                })
+                .count()
+        })
+    });
+    group.finish();
+}
+
+// the Suggestion with its text and highlights against only the score
+fn score_only(c: &mut Criterion) {
+    let config = ScoringConfig::default();
+    let candidates = candidates();
+    let prepared: Vec<PreparedCandidate> = candidates
+        .iter()
+        .map(|c| PreparedCandidate::new(c))
+        .collect();
+    let mut group = c.benchmark_group("score_only");
+    group.sample_size(20);
+    group.bench_function("suggestion", |b| {
+        b.iter(|| {
+            candidates
+                .iter()
+                .zip(&prepared)
+                .filter_map(|(c, prepared)| {
+                    algorithms::fuzzy_match_prepared(black_box("ther"), c, prepared, &config)
+                })
+                .map(|sug| sug.score)
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("score", |b| {
+        b.iter(|| {
+            candidates
+                .iter()
+                .zip(&prepared)
+                .filter_map(|(c, prepared)| {
+                    algorithms::fuzzy_score_prepared(black_box("ther"), c, prepared, &config)
+                })
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+// the byte based path ASCII options take against the char based one for any other text
+fn ascii(c: &mut Criterion) {
+    let config = ScoringConfig::default();
+    let candidates = candidates();
+    let fast: Vec<PreparedCandidate> = candidates
+        .iter()
+        .map(|c| PreparedCandidate::new(c))
+        .collect();
+    let general: Vec<PreparedCandidate> = fast
+        .iter()
+        .map(|prepared| PreparedCandidate {
+            ascii: false,
+            ..prepared.clone()
+        })
+        .collect();
+    let mut group = c.benchmark_group("ascii");
+    group.sample_size(20);
+    for (name, prepared) in [("fast", &fast), ("general", &general)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                candidates
+                    .iter()
+                    .zip(prepared)
+                    .filter_map(|(c, prepared)| {
+                        algorithms::fuzzy_score_prepared(black_box("ther"), c, prepared, &config)
+                    })
+                    .sum::<usize>()
+            })
+        });
+    }
+    group.finish();
+}
+
+// a 20 char query with the edit distance pass skipped past 8 chars (the default) and never
+fn long_query(c: &mut Criterion) {
+    let candidates = candidates();
+    let prepared: Vec<PreparedCandidate> = candidates
+        .iter()
+        .map(|c| PreparedCandidate::new(c))
+        .collect();
+    let mut group = c.benchmark_group("long_query");
+    group.sample_size(20);
+    for (name, max_query) in [("skipped", 8), ("edit_distance", usize::MAX)] {
+        let config = ScoringConfig {
+            edit_distance_max_query: max_query,
+            ..ScoringConfig::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                candidates
+                    .iter()
+                    .zip(&prepared)
+                    .filter_map(|(c, prepared)| {
+                        let query = black_box("there were about the");
+                        algorithms::fuzzy_score_prepared(query, c, prepared, &config)
+                    })
+                    .sum::<usize>()
+            })
+        });
+    }
+    group.finish();
+}
+
+type ScoreFn = fn(&str, &str, &PreparedCandidate, &ScoringConfig) -> usize;
+
+// a 20 char query, which most options miss a char of, with and without the missing chars gate
+fn missing_chars(c: &mut Criterion) {
+    let candidates = candidates();
+    let prepared: Vec<PreparedCandidate> = candidates
+        .iter()
+        .map(|c| PreparedCandidate::new(c))
+        .collect();
+    let mut group = c.benchmark_group("missing_chars");
+    group.sample_size(20);
+    let config = ScoringConfig::default();
+    let gated: ScoreFn = |q, c, prepared, config| {
+        algorithms::fuzzy_score_prepared(q, c, prepared, config).unwrap_or(0)
+    };
+    let ungated: ScoreFn =
+        |q, c, prepared, config| algorithms::explain_ungated(q, c, prepared, config).total();
+    for (name, score) in [("gated", gated), ("ungated", ungated)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                candidates
+                    .iter()
+                    .zip(&prepared)
+                    .map(|(c, prepared)| {
+                        score(black_box("quickly jumping foxes"), c, prepared, &config)
+                    })
+                    .sum::<usize>()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    edit_distance,
+    prepared,
+    score_only,
+    ascii,
+    long_query,
+    missing_chars
+);
+criterion_main!(benches);
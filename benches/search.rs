@@ -0,0 +1,52 @@
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use fuzzyq::algorithms::FuzzyScorer;
+use fuzzyq::search;
+use fuzzyq::structs::{Candidate, ScoringConfig, SearchConfig};
+
+const WORDS: &str = include_str!("../words.txt");
+
+// pairs of words, so the lists grow past the 20k words without repeating an option
+fn options(count: usize) -> Vec<Candidate> {
+    let words: Vec<&str> = WORDS.lines().collect();
+    (0..count)
+        .map(|i| {
+            let text = format!("{} {}", words[i % words.len()], words[i / words.len()]);
+            Candidate::new(text, None, Vec::new())
+        })
+        .collect()
+}
+
+// the suggestions with their cloned texts against the references best_candidates returns
+fn results(c: &mut Criterion) {
+    let scoring = ScoringConfig::default();
+    let scorer = FuzzyScorer { config: &scoring };
+    let config = SearchConfig::default();
+    let mut group = c.benchmark_group("results");
+    for count in [1_000, 10_000, 100_000] {
+        let options = options(count);
+        group.bench_with_input(BenchmarkId::new("cloned", count), &options, |b, options| {
+            b.iter(|| search::get_suggestions(black_box("ther"), options, &scorer, &config))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("indexed", count),
+            &options,
+            |b, options| {
+                b.iter(|| {
+                    search::best_candidates(
+                        black_box("ther"),
+                        options,
+                        &scorer,
+                        &config,
+                        config.limit,
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, results);
+criterion_main!(benches);
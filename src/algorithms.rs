@@ -1,100 +1,1162 @@
-use crate::structs::Suggestion;
+use crate::profile::{self, Pass};
+use crate::structs::{PreparedCandidate, PreparedSlug, ScoreBreakdown, ScoringConfig, Suggestion};
+use caseless::Caseless;
+use num_traits::Float;
+use serde::Deserialize;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::str::FromStr;
+use unicode_normalization::char::{canonical_combining_class, compose, is_combining_mark};
+use unicode_normalization::{IsNormalized, UnicodeNormalization, is_nfc_quick};
+use unicode_segmentation::UnicodeSegmentation;
 
-pub fn normalize_embeddings(embeddings: &mut [Vec<f32>]) {
-    for emb in embeddings.iter_mut() {
-        let norm = emb.iter().map(|x| x * x).sum::<f32>().sqrt();
-        if norm > 0.0 {
-            for v in emb.iter_mut() {
-                *v /= norm;
+pub const MAX_SCORE: usize = 1000;
+
+// the ranking compares scores in 1/RANK_SCALE of a point, see Scorer::rank_score
+pub const RANK_SCALE: usize = 1000;
+
+pub trait Scorer {
+    fn score(&self, query: &str, candidate: &str) -> Option<Suggestion>;
+
+    // scorers that use query independent features override this to skip recomputing them
+    fn score_prepared(
+        &self,
+        query: &str,
+        candidate: &str,
+        _prepared: &PreparedCandidate,
+    ) -> Option<Suggestion> {
+        self.score(query, candidate)
+    }
+
+    // only the score, for ranking before the highlights of the shown suggestions are computed;
+    // scorers that can compute it without building a Suggestion override this
+    fn score_only(
+        &self,
+        query: &str,
+        candidate: &str,
+        prepared: &PreparedCandidate,
+    ) -> Option<usize> {
+        self.score_prepared(query, candidate, prepared)
+            .map(|sug| sug.score)
+    }
+
+    // score_only in 1/RANK_SCALE points, which is what the options are ranked by. Scorers with a
+    // continuous score override this to keep the precision the whole points of the shown score
+    // round away, so e.g. similarities that differ in the fourth decimal still rank in order.
+    fn rank_score(
+        &self,
+        query: &str,
+        candidate: &str,
+        prepared: &PreparedCandidate,
+    ) -> Option<usize> {
+        self.score_only(query, candidate, prepared)
+            .map(|score| score * RANK_SCALE)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct FuzzyScorer<'a> {
+    pub config: &'a ScoringConfig,
+}
+
+impl Scorer for FuzzyScorer<'_> {
+    fn score(&self, query: &str, candidate: &str) -> Option<Suggestion> {
+        fuzzy_match(query, candidate, self.config)
+    }
+
+    fn score_prepared(
+        &self,
+        query: &str,
+        candidate: &str,
+        prepared: &PreparedCandidate,
+    ) -> Option<Suggestion> {
+        fuzzy_match_prepared(query, candidate, prepared, self.config)
+    }
+
+    fn score_only(
+        &self,
+        query: &str,
+        candidate: &str,
+        prepared: &PreparedCandidate,
+    ) -> Option<usize> {
+        fuzzy_score_prepared(query, candidate, prepared, self.config)
+    }
+}
+
+// Only candidates within max_distance edits of the query match, ranked by ascending distance.
+// Meant for spell correction, none of the fuzzy passes apply.
+pub struct EditDistanceScorer {
+    pub max_distance: usize,
+    // the query it's made for and its lowercase_nfc form, so it isn't normalized per candidate
+    query: (String, String),
+}
+
+impl EditDistanceScorer {
+    pub fn new(query: &str, max_distance: usize) -> Self {
+        EditDistanceScorer {
+            max_distance,
+            query: (query.to_string(), lowercase_nfc(query)),
+        }
+    }
+
+    fn distance_score(&self, query: &str, lowercase: &str) -> Option<usize> {
+        let normalized;
+        let query = if query == self.query.0 {
+            &self.query.1
+        } else {
+            normalized = lowercase_nfc(query);
+            &normalized
+        };
+        let dist = levenshtein_bounded(query, lowercase, self.max_distance)?;
+        Some(MAX_SCORE * (self.max_distance + 1 - dist) / (self.max_distance + 1))
+    }
+}
+
+// the query lowercased like PreparedCandidate::lowercase, which is of the NFC form
+pub fn lowercase_nfc(query: &str) -> String {
+    nfc(query).map_or_else(|| query.to_lowercase(), |(q, _)| q.to_lowercase())
+}
+
+impl Scorer for EditDistanceScorer {
+    fn score(&self, query: &str, candidate: &str) -> Option<Suggestion> {
+        self.score_prepared(query, candidate, &PreparedCandidate::new(candidate))
+    }
+
+    fn score_prepared(
+        &self,
+        query: &str,
+        candidate: &str,
+        prepared: &PreparedCandidate,
+    ) -> Option<Suggestion> {
+        Some(Suggestion {
+            text: candidate.to_string(),
+            score: self.distance_score(query, &prepared.lowercase)?,
+            ..Default::default()
+        })
+    }
+
+    fn score_only(
+        &self,
+        query: &str,
+        _candidate: &str,
+        prepared: &PreparedCandidate,
+    ) -> Option<usize> {
+        self.distance_score(query, &prepared.lowercase)
+    }
+}
+
+pub struct SemanticScorer<'a> {
+    pub query_embedding: &'a [f32],
+    pub option_embeddings: &'a HashMap<String, Vec<f32>>,
+    // whether the option and query embeddings have unit length
+    pub normalized: bool,
+    // used for highlighting and for the lexical fallback
+    pub config: &'a ScoringConfig,
+    // options with an embedding less similar than this don't match, 0.0 keeps every positive one
+    pub threshold: f32,
+}
+
+impl SemanticScorer<'_> {
+    // The similarity to the option embedding, None when it's below the threshold. It's compared
+    // before rounding to points, so a similarity equal to the threshold passes. Negative
+    // similarities score 0, which a threshold of 0 still keeps.
+    fn passing_similarity(&self, emb: &[f32]) -> Option<f32> {
+        let similarity = similarity(self.query_embedding, emb, self.normalized);
+        (self.threshold <= 0.0 || similarity >= self.threshold.min(1.0)).then_some(similarity)
+    }
+}
+
+impl Scorer for SemanticScorer<'_> {
+    fn score(&self, query: &str, candidate: &str) -> Option<Suggestion> {
+        self.score_prepared(query, candidate, &PreparedCandidate::new(candidate))
+    }
+
+    fn score_prepared(
+        &self,
+        query: &str,
+        candidate: &str,
+        prepared: &PreparedCandidate,
+    ) -> Option<Suggestion> {
+        match self.option_embeddings.get(candidate) {
+            Some(emb) => {
+                let similarity = self.passing_similarity(emb)?;
+                Some(semantic_suggestion(
+                    query,
+                    candidate,
+                    prepared,
+                    similarity,
+                    self.config,
+                ))
+            }
+            // candidates without an embedding fall back to lexical ranking
+            None => fuzzy_match_prepared(query, candidate, prepared, self.config),
+        }
+    }
+
+    fn score_only(
+        &self,
+        query: &str,
+        candidate: &str,
+        prepared: &PreparedCandidate,
+    ) -> Option<usize> {
+        match self.option_embeddings.get(candidate) {
+            Some(emb) => Some(similarity_points(self.passing_similarity(emb)?, MAX_SCORE)),
+            None => fuzzy_score_prepared(query, candidate, prepared, self.config),
+        }
+    }
+
+    fn rank_score(
+        &self,
+        query: &str,
+        candidate: &str,
+        prepared: &PreparedCandidate,
+    ) -> Option<usize> {
+        match self.option_embeddings.get(candidate) {
+            Some(emb) => {
+                let similarity = self.passing_similarity(emb)?;
+                Some(similarity_points(similarity, MAX_SCORE * RANK_SCALE))
             }
+            None => fuzzy_score_prepared(query, candidate, prepared, self.config)
+                .map(|score| score * RANK_SCALE),
         }
     }
 }
 
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-    // we assume normalized vector to apply function simplification (not dividing by norms)
-    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
-    dot
+// Sums the sub-scores, each normalized to 0.0 - 1.0, weighted and rescaled to the usual score range.
+// A scorer that returns None drops the candidate, even one another scorer matches exactly, as
+// EditDistanceScorer does past max_distance and SemanticScorer below its threshold. FuzzyScorer
+// always returns a score, so a lexical non-match only adds 0. Scorers with a weight of 0 are left
+// out, they would add nothing but the time spent scoring every option.
+pub struct CompositeScorer<'a> {
+    scorers: Vec<(Box<dyn Scorer + 'a>, f32)>,
 }
 
-pub fn fuzzy_match(query: &str, candidate: &str) -> Option<Suggestion> {
-    let q = query.to_lowercase();
-    let c = candidate.to_lowercase();
+impl<'a> CompositeScorer<'a> {
+    pub fn new(mut scorers: Vec<(Box<dyn Scorer + 'a>, f32)>) -> Self {
+        scorers.retain(|(_, weight)| *weight != 0.0);
+        Self { scorers }
+    }
+}
 
-    let mut score: usize = 0;
-    let mut match_indices: Vec<usize> = Vec::new();
+impl Scorer for CompositeScorer<'_> {
+    fn score(&self, query: &str, candidate: &str) -> Option<Suggestion> {
+        self.score_prepared(query, candidate, &PreparedCandidate::new(candidate))
+    }
 
-    // 1. Exact match
-    if q == c {
-        score = 1000;
-        match_indices = (0..q.len()).collect();
-        return Some(Suggestion {
+    fn score_prepared(
+        &self,
+        query: &str,
+        candidate: &str,
+        prepared: &PreparedCandidate,
+    ) -> Option<Suggestion> {
+        let total_weight: f32 = self.scorers.iter().map(|(_, weight)| weight).sum();
+        if total_weight <= 0.0 {
+            return None;
+        }
+
+        let mut blended = 0.0;
+        let mut match_indices: Vec<usize> = Vec::new();
+        let mut unmatched_query_indices: Option<Vec<usize>> = None;
+        let mut semantic_only = false;
+        for (scorer, weight) in &self.scorers {
+            let sug = scorer.score_prepared(query, candidate, prepared)?;
+            blended += weight * sug.normalized_score();
+            semantic_only |= sug.semantic_only;
+            match_indices.extend(sug.match_indices);
+            unmatched_query_indices.get_or_insert(sug.unmatched_query_indices);
+        }
+        let unmatched_query_indices = unmatched_query_indices?;
+
+        match_indices.sort_unstable();
+        match_indices.dedup();
+        Some(Suggestion {
             text: candidate.to_string(),
+            semantic_only: semantic_only && match_indices.is_empty(),
             match_indices,
-            score,
-        });
+            score: (blended / total_weight * MAX_SCORE as f32).round() as usize,
+            unmatched_query_indices,
+            ..Default::default()
+        })
     }
 
-    // 2. Substring match
-    if let Some(pos) = c.find(&q) {
-        score += 200;
-        score += q.len() * 10;
-        score += 100usize.saturating_sub(pos); // earlier is better
-        match_indices = (pos..pos + q.len()).collect();
+    fn score_only(
+        &self,
+        query: &str,
+        candidate: &str,
+        prepared: &PreparedCandidate,
+    ) -> Option<usize> {
+        let total_weight: f32 = self.scorers.iter().map(|(_, weight)| weight).sum();
+        if total_weight <= 0.0 {
+            return None;
+        }
+
+        // same blend as score_prepared, so both agree on every candidate
+        let mut blended = 0.0;
+        for (scorer, weight) in &self.scorers {
+            let score = scorer.score_only(query, candidate, prepared)?;
+            blended += weight * score.min(MAX_SCORE) as f32 / MAX_SCORE as f32;
+        }
+        Some((blended / total_weight * MAX_SCORE as f32).round() as usize)
     }
 
-    // 3. Prefix bonus
-    if c.starts_with(&q) {
-        score += 150;
+    // the blend of score_only on the finer scale of the sub-scores
+    fn rank_score(
+        &self,
+        query: &str,
+        candidate: &str,
+        prepared: &PreparedCandidate,
+    ) -> Option<usize> {
+        let total_weight: f32 = self.scorers.iter().map(|(_, weight)| weight).sum();
+        if total_weight <= 0.0 {
+            return None;
+        }
+
+        let max_rank = MAX_SCORE * RANK_SCALE;
+        let mut blended = 0.0;
+        for (scorer, weight) in &self.scorers {
+            let rank = scorer.rank_score(query, candidate, prepared)?;
+            blended += weight * rank.min(max_rank) as f32 / max_rank as f32;
+        }
+        Some((blended / total_weight * max_rank as f32).round() as usize)
     }
+}
 
-    // 4. Subsequence match (always attempt)
-    let mut last = 0;
-    let mut gaps = 0;
+// The embedding math is generic over the float type so f64 embeddings keep their precision,
+// the rest of fuzzyQ uses f32.
+pub fn normalize_embeddings<T: Float>(embeddings: &mut [Vec<T>]) {
+    for emb in embeddings.iter_mut() {
+        normalize(emb);
+    }
+}
 
-    for qc in q.chars() {
-        if let Some(pos) = c[last..].find(qc) {
-            let real = last + pos;
-            if let Some(prev) = match_indices.last() {
-                gaps += real.saturating_sub(*prev + 1);
-            }
-            match_indices.push(real);
-            last = real + 1;
+// like normalize_embeddings, but keeps the originals
+pub fn normalized<T: Float>(embeddings: &[Vec<T>]) -> Vec<Vec<T>> {
+    let mut copies = embeddings.to_vec();
+    normalize_embeddings(&mut copies);
+    copies
+}
+
+// NaN and infinite values are zeroed first, a single one would otherwise turn the whole vector NaN
+fn normalize<T: Float>(emb: &mut [T]) {
+    for v in emb.iter_mut() {
+        if !v.is_finite() {
+            *v = T::zero();
+        }
+    }
+    let norm = norm(emb);
+    if norm > T::zero() {
+        for v in emb.iter_mut() {
+            *v = *v / norm;
         }
     }
+}
+
+fn norm<T: Float>(v: &[T]) -> T {
+    v.iter().fold(T::zero(), |sum, &x| sum + x * x).sqrt()
+}
+
+// true when every vector has unit length, so the cheaper cosine_similarity can be used
+pub fn embeddings_normalized<'a, T: Float + 'a>(
+    embeddings: impl IntoIterator<Item = &'a Vec<T>>,
+) -> bool {
+    let tolerance = T::from(1e-3).unwrap_or_else(T::epsilon);
+    embeddings
+        .into_iter()
+        .all(|emb| (norm(emb) - T::one()).abs() < tolerance)
+}
+
+fn cosine_similarity<T: Float>(a: &[T], b: &[T]) -> T {
+    // we assume normalized vector to apply function simplification (not dividing by norms)
+    a.iter().zip(b).fold(T::zero(), |dot, (&x, &y)| dot + x * y)
+}
+
+// an embeddings file entry ordered by its similarity to some target
+struct Neighbor<'a> {
+    key: &'a str,
+    similarity: f32,
+}
+
+impl Ord for Neighbor<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.similarity.total_cmp(&other.similarity)
+    }
+}
+
+impl PartialOrd for Neighbor<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-    let matched = match_indices.len();
-    if matched > 0 {
-        score += matched * 10;
-        score += 50usize.saturating_sub(gaps);
+impl PartialEq for Neighbor<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
+}
 
-    // 5. Edit distance bonus (handles "heyp" -> "hey")
-    let dist = levenshtein(&q, &c);
-    if dist <= 2 {
-        score += (3 - dist) * 30;
+impl Eq for Neighbor<'_> {}
+
+// the k entries most similar to the target, most similar first, skipping the target's own key
+pub fn nearest_neighbors<'a>(
+    key: &str,
+    target: &[f32],
+    embeddings: &'a [(String, Vec<f32>)],
+    k: usize,
+) -> Vec<(&'a str, f32)> {
+    // min-heap, the least similar kept entry is on top and gets evicted first
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for (other, emb) in embeddings {
+        if other == key {
+            continue;
+        }
+        heap.push(Reverse(Neighbor {
+            key: other,
+            similarity: cosine_similarity_full(target, emb),
+        }));
+        if heap.len() > k {
+            heap.pop();
+        }
     }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(neighbor)| (neighbor.key, neighbor.similarity))
+        .collect()
+}
 
-    // 6. clamp score to 0 - 1000
-    if score > 1000 {
-        score = 1000;
+pub fn cosine_similarity_full<T: Float>(a: &[T], b: &[T]) -> T {
+    let norms = norm(a) * norm(b);
+    if norms == T::zero() {
+        return T::zero();
     }
+    cosine_similarity(a, b) / norms
+}
 
+// Scoring passes are additive:
+// - exact match short-circuits with the maximum score
+// - substring and prefix bonuses reward a contiguous occurrence of the query
+// - the subsequence pass runs on its own and rewards matched characters and tight gaps
+// - the acronym bonus rewards a query spelling out the initials of the candidate's words
+// - the edit distance bonus catches small typos
+// The substring and subsequence passes can find the same candidate characters, every candidate
+// character counts towards the matched total once. Only one alignment is highlighted: the
+// substring when there is one, otherwise the acronym initials or the subsequence.
+// Every pass except the exact match can be switched off in the ScoringConfig.
+// Most a pass adds, for a query of n bytes:
+// - exact match: MAX_SCORE, nothing else is added
+// - substring: 200 + 10n + 100 (for a match at the very start, or end with prefer_end)
+// - prefix: 150
+// - subsequence: 10 per matched candidate character + 50 without gaps, where a partial alignment
+//   only counts together with another pass
+// - acronym: 250
+// - edit distance: 60 (one edit away, the exact match is handled before)
+// A candidate no pass finds the whole query in scores 0, which means it doesn't match.
+// The sum is clamped to MAX_SCORE, so scores are always within 0 - MAX_SCORE and
+// Suggestion::normalized_score maps them to 0.0 - 1.0.
+// With prefer_end the substring position bonus counts from the end of the candidate and the
+// prefix bonus applies to the last path component, so "main" ranks src/main.rs first.
+// A position_decay d above 0 makes the 10 points of a matched character in the substring and
+// subsequence passes 10 / (1 + d * p) instead, for a character p bytes from the start (or the end
+// with prefer_end), so earlier matches rank gradually higher rather than only through the prefix.
+pub fn fuzzy_match(query: &str, candidate: &str, config: &ScoringConfig) -> Option<Suggestion> {
+    fuzzy_match_prepared(query, candidate, &PreparedCandidate::new(candidate), config)
+}
+
+pub fn fuzzy_match_prepared(
+    query: &str,
+    candidate: &str,
+    prepared: &PreparedCandidate,
+    config: &ScoringConfig,
+) -> Option<Suggestion> {
+    let mut highlights = Highlights::default();
+    let score =
+        fuzzy_passes::<true>(query, candidate, prepared, config, Some(&mut highlights)).total();
+    let match_indices = if prepared.ascii {
+        highlights.match_indices
+    } else {
+        whole_graphemes(candidate, &highlights.match_indices)
+    };
     Some(Suggestion {
         text: candidate.to_string(),
         match_indices,
         score,
+        unmatched_query_indices: highlights.unmatched_query_indices,
+        ..Default::default()
     })
 }
 
-fn levenshtein(a: &str, b: &str) -> usize {
+// Every char of each grapheme cluster that one of the sorted indices falls in, so e.g. a flag or an
+// emoji with a skin tone (several chars forming one symbol) is matched as a whole
+fn whole_graphemes(text: &str, indices: &[usize]) -> Vec<usize> {
+    let mut pending = indices.iter().copied().peekable();
+    let mut whole = Vec::with_capacity(indices.len());
+    for (start, grapheme) in text.grapheme_indices(true) {
+        let end = start + grapheme.len();
+        let mut matched = false;
+        while pending.next_if(|&idx| idx < end).is_some() {
+            matched = true;
+        }
+        if matched {
+            whole.extend(grapheme.char_indices().map(|(i, _)| start + i));
+        }
+        if pending.peek().is_none() {
+            break;
+        }
+    }
+    whole
+}
+
+// The candidate position of each query character, taking the first occurrence after the previous
+// match. Characters that don't occur after it are skipped and stay None.
+fn leftmost_subsequence(q: &str, c: &str) -> Vec<Option<usize>> {
+    let mut last = 0;
+    q.chars()
+        .map(|qc| {
+            let pos = last + c[last..].find(qc)?;
+            last = pos + qc.len_utf8();
+            Some(pos)
+        })
+        .collect()
+}
+
+// The query characters, with repeats, that don't occur anywhere in the candidate. Each one is an
+// edit, so it's also a lower bound of the edit distance.
+fn missing_chars(q: &str, c: &str, ascii: bool) -> usize {
+    if ascii {
+        let present = c.bytes().fold(0u128, |set, byte| set | 1 << byte);
+        q.bytes().filter(|&byte| present & 1 << byte == 0).count()
+    } else {
+        q.chars().filter(|&qc| !c.contains(qc)).count()
+    }
+}
+
+// Positions of the query characters in the shortest window of the candidate that holds all of
+// them in order, None when they don't all occur. The leftmost alignment can be much wider, e.g.
+// "lo" in "large lot" or a repeated "ll" in "a low ball".
+fn tightest_subsequence(q: &str, c: &str) -> Option<Vec<Option<usize>>> {
+    let first_len = q.chars().next()?.len_utf8();
+    let last_len = q.chars().next_back()?.len_utf8();
+    let mut best: Option<Vec<usize>> = None;
+    let mut positions: Vec<usize> = Vec::with_capacity(q.len());
+    let mut from = 0;
+    'windows: loop {
+        // the earliest end of an alignment starting at or after from
+        positions.clear();
+        let mut last = from;
+        for qc in q.chars() {
+            let Some(pos) = c[last..].find(qc) else {
+                break 'windows;
+            };
+            positions.push(last + pos);
+            last += pos + qc.len_utf8();
+        }
+        // walking back from that end moves every earlier character as far right as it goes
+        let mut limit = positions[positions.len() - 1];
+        for (pos, qc) in positions.iter_mut().rev().zip(q.chars().rev()).skip(1) {
+            // the forward position is before limit, so there always is one
+            *pos = c[..limit].rfind(qc).unwrap_or(*pos);
+            limit = *pos;
+        }
+        let span = positions[positions.len() - 1] - positions[0];
+        if best
+            .as_ref()
+            .is_none_or(|best| span < best[best.len() - 1] - best[0])
+        {
+            best = Some(positions.clone());
+        }
+        // a window without gaps can't be beaten
+        if span + last_len == q.len() {
+            break;
+        }
+        from = positions[0] + first_len;
+    }
+    best.map(|positions| positions.into_iter().map(Some).collect())
+}
+
+// the score fuzzy_match gives, without allocating the Suggestion and its highlights
+pub fn fuzzy_score_prepared(
+    query: &str,
+    candidate: &str,
+    prepared: &PreparedCandidate,
+    config: &ScoringConfig,
+) -> Option<usize> {
+    Some(fuzzy_passes::<true>(query, candidate, prepared, config, None).total())
+}
+
+// what each pass of fuzzy_match adds for this pair, for tuning the ScoringConfig
+pub fn explain(query: &str, candidate: &str, config: &ScoringConfig) -> ScoreBreakdown {
+    fuzzy_passes::<true>(
+        query,
+        candidate,
+        &PreparedCandidate::new(candidate),
+        config,
+        None,
+    )
+}
+
+// the passes of fuzzy_score_prepared without the missing chars gate, which must not change the
+// breakdown, to measure the time the gate saves in benches/scoring.rs
+#[cfg(any(test, feature = "bench"))]
+pub fn explain_ungated(
+    query: &str,
+    candidate: &str,
+    prepared: &PreparedCandidate,
+    config: &ScoringConfig,
+) -> ScoreBreakdown {
+    fuzzy_passes::<false>(query, candidate, prepared, config, None)
+}
+
+#[derive(Default)]
+struct Highlights {
+    match_indices: Vec<usize>,
+    unmatched_query_indices: Vec<usize>,
+    // the match indices are word initials, which are found in the candidate itself (to see its
+    // case) rather than in its folded form
+    initials: bool,
+}
+
+// runs the scoring passes, filling in the highlights only when asked for them
+fn fuzzy_passes<const GATE: bool>(
+    query: &str,
+    candidate: &str,
+    prepared: &PreparedCandidate,
+    config: &ScoringConfig,
+    highlights: Option<&mut Highlights>,
+) -> ScoreBreakdown {
+    if config.slug {
+        let on_the_fly;
+        let slug = match &prepared.slug {
+            Some(slug) => slug.as_ref(),
+            None => {
+                on_the_fly = PreparedSlug::new(candidate, config);
+                &on_the_fly
+            }
+        };
+        return slug_passes::<GATE>(query, slug, config, highlights);
+    }
+    // precomposed and decomposed accents match alike, the passes run on the NFC forms
+    let query_nfc = nfc(query);
+    if query_nfc.is_none() && prepared.nfc.is_none() {
+        return cased_passes::<GATE>(query, candidate, prepared, config, highlights);
+    }
+    let q = query_nfc.as_ref().map_or(query, |(q, _)| q.as_str());
+    let c = prepared.nfc.as_ref().map_or(candidate, |(c, _)| c.as_str());
+    let Some(highlights) = highlights else {
+        return cased_passes::<GATE>(q, c, prepared, config, None);
+    };
+    let breakdown = cased_passes::<GATE>(q, c, prepared, config, Some(highlights));
+
+    // map the positions back to the original texts, which are what is displayed
+    // e.g. all of "e" and its combining accent for a matched "é"
+    if let Some((_, offsets)) = &prepared.nfc {
+        let mut original = Vec::with_capacity(highlights.match_indices.len());
+        for &idx in &highlights.match_indices {
+            // every byte of a multibyte char can be listed, it's the same original char
+            let Some(&start) = offsets.get(idx) else {
+                continue;
+            };
+            if original.last().is_some_and(|&last| last >= start) {
+                continue;
+            }
+            let end = offsets[idx..]
+                .iter()
+                .find(|&&offset| offset != start)
+                .copied()
+                .unwrap_or(candidate.len());
+            original.extend(candidate[start..end].char_indices().map(|(i, _)| start + i));
+        }
+        highlights.match_indices = original;
+    }
+    if let Some((q, offsets)) = &query_nfc {
+        for idx in highlights.unmatched_query_indices.iter_mut() {
+            *idx = original_char_index(q, offsets, query, *idx);
+        }
+        highlights.unmatched_query_indices.dedup();
+    }
+    breakdown
+}
+
+// The passes on the slug forms. Highlights on a '-' are dropped, it stands for a whole run of
+// characters; the others go to the original character, e.g. all of an "é" for a matched "e".
+fn slug_passes<const GATE: bool>(
+    query: &str,
+    slug_form: &PreparedSlug,
+    config: &ScoringConfig,
+    highlights: Option<&mut Highlights>,
+) -> ScoreBreakdown {
+    let (q, query_offsets) = slug(query);
+    let c = slug_form.text.as_str();
+    let Some(highlights) = highlights else {
+        return cased_passes::<GATE>(&q, c, &slug_form.prepared, config, None);
+    };
+    let breakdown = cased_passes::<GATE>(&q, c, &slug_form.prepared, config, Some(highlights));
+
+    highlights.match_indices = highlights
+        .match_indices
+        .iter()
+        .filter(|&&idx| c.as_bytes().get(idx).is_some_and(|&byte| byte != b'-'))
+        .map(|&idx| slug_form.offsets[idx])
+        .collect();
+    highlights.match_indices.dedup();
+    for idx in highlights.unmatched_query_indices.iter_mut() {
+        *idx = original_char_index(&q, &query_offsets, query, *idx);
+    }
+    highlights.unmatched_query_indices.dedup();
+    breakdown
+}
+
+fn cased_passes<const GATE: bool>(
+    query: &str,
+    candidate: &str,
+    prepared: &PreparedCandidate,
+    config: &ScoringConfig,
+    highlights: Option<&mut Highlights>,
+) -> ScoreBreakdown {
+    if !config.case_folding && prepared.ascii && query.is_ascii() {
+        let q = query.to_ascii_lowercase();
+        return score_passes::<GATE>(&q, &prepared.lowercase, candidate, true, config, highlights);
+    }
+    // the folded candidate, as prepared when the options were loaded or else computed here
+    let on_the_fly;
+    let folded = match &prepared.folded {
+        Some(folded) if config.case_folding => Some(folded),
+        None if config.case_folding => {
+            on_the_fly = case_fold(candidate);
+            Some(&on_the_fly)
+        }
+        _ => None,
+    };
+    let Some(highlights) = highlights else {
+        if let Some((c, _)) = folded {
+            let (q, _) = case_fold(query);
+            return score_passes::<GATE>(&q, c, candidate, false, config, None);
+        }
+        let q = query.to_lowercase();
+        return score_passes::<GATE>(&q, &prepared.lowercase, candidate, false, config, None);
+    };
+
+    let ((q, query_offsets), (c, candidate_offsets)) = if let Some(folded) = folded {
+        (case_fold(query), folded.clone())
+    } else {
+        // the texts of str::to_lowercase, which lowercases a final sigma to "ς", like without
+        // the highlights, with the offsets of the chars lowercased one by one
+        let (_, query_offsets) = lowercase(query);
+        let (_, candidate_offsets) = lowercase(candidate);
+        (
+            (query.to_lowercase(), query_offsets),
+            (prepared.lowercase.clone(), candidate_offsets),
+        )
+    };
+    let breakdown = score_passes::<GATE>(&q, &c, candidate, false, config, Some(highlights));
+
+    // the passes ran on the folded text, whose chars can be longer than the original ones (e.g.
+    // "i̇" for "İ"), map their positions back to the original
+    if !highlights.initials {
+        for idx in highlights.match_indices.iter_mut() {
+            *idx = candidate_offsets[*idx];
+        }
+        highlights.match_indices.dedup();
+    }
+    for idx in highlights.unmatched_query_indices.iter_mut() {
+        *idx = original_char_index(&q, &query_offsets, query, *idx);
+    }
+    highlights.unmatched_query_indices.dedup();
+    breakdown
+}
+
+// the char index in the original text of the char at idx in its transformed form, given the
+// byte offsets of case_fold or nfc
+fn original_char_index(transformed: &str, offsets: &[usize], original: &str, idx: usize) -> usize {
+    let transformed_byte = transformed
+        .char_indices()
+        .nth(idx)
+        .map_or(transformed.len(), |(byte, _)| byte);
+    let byte = offsets
+        .get(transformed_byte)
+        .copied()
+        .unwrap_or(original.len());
+    original[..byte].chars().count()
+}
+
+// Full Unicode case folding, which also equates e.g. "ß" with "ss". Besides the folded text it
+// returns, for every folded byte, the byte offset of the character it came from.
+pub fn case_fold(text: &str) -> (String, Vec<usize>) {
+    fold_chars(text, |ch| std::iter::once(ch).default_case_fold())
+}
+
+// char::to_lowercase, with the offsets of case_fold
+fn lowercase(text: &str) -> (String, Vec<usize>) {
+    fold_chars(text, char::to_lowercase)
+}
+
+fn fold_chars<I: Iterator<Item = char>>(
+    text: &str,
+    fold: impl Fn(char) -> I,
+) -> (String, Vec<usize>) {
+    let mut folded = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len());
+    for (i, ch) in text.char_indices() {
+        for folded_ch in fold(ch) {
+            folded.push(folded_ch);
+            offsets.resize(folded.len(), i);
+        }
+    }
+    (folded, offsets)
+}
+
+// Lowercase letters and digits without their diacritics, with every run of other characters in
+// between as a single '-', e.g. "hello-world" for "Hello, World!". Like case_fold it also returns
+// the byte offset of the original character every slug byte came from, for a '-' the first of its
+// run.
+pub fn slug(text: &str) -> (String, Vec<usize>) {
+    let mut slug = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len());
+    let mut separator: Option<usize> = None;
+    for (i, ch) in text.char_indices() {
+        if ch.is_alphanumeric() {
+            if let Some(start) = separator.take()
+                && !slug.is_empty()
+            {
+                slug.push('-');
+                offsets.push(start);
+            }
+            let plain = std::iter::once(ch)
+                .nfd()
+                .filter(|&part| !is_combining_mark(part))
+                .flat_map(char::to_lowercase);
+            for part in plain {
+                slug.push(part);
+                offsets.resize(slug.len(), i);
+            }
+        } else if !is_combining_mark(ch) {
+            // a decomposed accent is stripped like a precomposed one, not a separator
+            separator.get_or_insert(i);
+        }
+    }
+    (slug, offsets)
+}
+
+// The NFC form of a text that isn't in it yet, with like case_fold the byte offset of the original
+// character every normalized byte came from. Composition only crosses from one starter (a char
+// without combining class) to the next for e.g. decomposed Hangul, so apart from that each starter
+// with its combining marks is normalized on its own.
+pub fn nfc(text: &str) -> Option<(String, Vec<usize>)> {
+    if is_nfc_quick(text.chars()) == IsNormalized::Yes {
+        return None;
+    }
+    let mut starts: Vec<usize> = text
+        .char_indices()
+        .filter(|&(i, ch)| i == 0 || canonical_combining_class(ch) == 0)
+        .map(|(i, _)| i)
+        .collect();
+    starts.push(text.len());
+
+    let mut normalized = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len());
+    for bounds in starts.windows(2) {
+        let (start, end) = (bounds[0], bounds[1]);
+        for (k, ch) in text[start..end].nfc().enumerate() {
+            if k == 0
+                && let Some(last) = normalized.chars().next_back()
+                && let Some(composed) = compose(last, ch)
+            {
+                let last_start = normalized.len() - last.len_utf8();
+                let offset = offsets[last_start];
+                normalized.truncate(last_start);
+                normalized.push(composed);
+                offsets.resize(normalized.len(), offset);
+                continue;
+            }
+            normalized.push(ch);
+            offsets.resize(normalized.len(), start);
+        }
+    }
+    (normalized != text).then_some((normalized, offsets))
+}
+
+// the points of a matched character at byte pos of c under the position decay
+fn position_points(pos: usize, c: &str, config: &ScoringConfig) -> f32 {
+    let distance = if config.prefer_end {
+        c.len().saturating_sub(pos + 1)
+    } else {
+        pos
+    };
+    10.0 / (1.0 + config.position_decay * distance as f32)
+}
+
+// The passes on an already lowercased or folded query and candidate. With ascii both are known
+// to be ASCII and the char based steps work on bytes instead.
+fn score_passes<const GATE: bool>(
+    q: &str,
+    c: &str,
+    candidate: &str,
+    ascii: bool,
+    config: &ScoringConfig,
+    mut highlights: Option<&mut Highlights>,
+) -> ScoreBreakdown {
+    let mut breakdown = ScoreBreakdown::default();
+
+    // 1. Exact match
+    if q == c {
+        if let Some(highlights) = highlights {
+            highlights.match_indices = (0..q.len()).collect();
+        }
+        breakdown.exact = MAX_SCORE;
+        return breakdown;
+    }
+
+    // 2. Substring match
+    let mut substring = 0..0;
+    if config.enable_substring {
+        let _timer = profile::timer(Pass::Substring);
+        let found = if config.prefer_end {
+            c.rfind(q)
+        } else {
+            c.find(q)
+        };
+        if let Some(pos) = found {
+            breakdown.substring += 200;
+            breakdown.substring += if config.position_decay > 0.0 {
+                (pos..pos + q.len())
+                    .map(|p| position_points(p, c, config))
+                    .sum::<f32>()
+                    .round() as usize
+            } else {
+                q.len() * 10
+            };
+            // earlier is better, or later with prefer_end
+            let distance = if config.prefer_end {
+                c.len() - (pos + q.len())
+            } else {
+                pos
+            };
+            breakdown.substring += 100usize.saturating_sub(distance);
+            substring = pos..pos + q.len();
+            if let Some(highlights) = highlights.as_deref_mut() {
+                highlights.match_indices.extend(substring.clone());
+            }
+        }
+    }
+
+    // 3. Prefix bonus
+    // with prefer_end the prefix is that of the last path component
+    let start = if config.prefer_end {
+        c.rfind(['/', '\\']).map_or(0, |sep| sep + 1)
+    } else {
+        0
+    };
+    if config.enable_prefix && c[start..].starts_with(q) {
+        breakdown.prefix += 150;
+    }
+
+    // with any query character missing from the candidate it isn't a whole subsequence, and with
+    // more than two it isn't within the edit distance either
+    let missing = if GATE { missing_chars(q, c, ascii) } else { 0 };
+    let query_chars = if ascii { q.len() } else { q.chars().count() };
+    // whether the edit distance pass (6.) runs
+    let edit_distance = config.enable_edit_distance
+        && query_chars <= config.edit_distance_max_query
+        && missing <= 2;
+    // a candidate missing a char isn't a substring, prefix or acronym match either, so without a
+    // chance of the edit distance bonus it doesn't match and only the highlights need the passes
+    if missing > 0 && !edit_distance && highlights.is_none() {
+        return ScoreBreakdown::default();
+    }
+
+    // 4. Subsequence match
+    let mut whole_subsequence = false;
+    if config.enable_subsequence {
+        let _timer = profile::timer(Pass::Subsequence);
+        // candidate characters already counted by the substring aren't counted again
+        let mut matched = substring.len();
+        // only summed with a position decay
+        let decays = config.position_decay > 0.0;
+        let mut decayed: f32 = if decays {
+            substring
+                .clone()
+                .map(|p| position_points(p, c, config))
+                .sum()
+        } else {
+            0.0
+        };
+        let mut prev: Option<usize> = None;
+        let mut gaps = 0;
+
+        let tightest = if missing == 0 {
+            tightest_subsequence(q, c)
+        } else {
+            None
+        };
+        whole_subsequence = tightest.is_some();
+        let aligned = tightest.unwrap_or_else(|| leftmost_subsequence(q, c));
+        for (i, real) in aligned.into_iter().enumerate() {
+            if let Some(real) = real {
+                if let Some(prev) = prev {
+                    gaps += real.saturating_sub(prev + 1);
+                }
+                if !substring.contains(&real) {
+                    matched += 1;
+                    if decays {
+                        decayed += position_points(real, c, config);
+                    }
+                    // a contiguous occurrence is the alignment shown when there is one
+                    if substring.is_empty()
+                        && let Some(highlights) = highlights.as_deref_mut()
+                    {
+                        highlights.match_indices.push(real);
+                    }
+                }
+                prev = Some(real);
+            } else if let Some(highlights) = highlights.as_deref_mut() {
+                highlights.unmatched_query_indices.push(i);
+            }
+        }
+
+        if matched > 0 {
+            breakdown.subsequence += if decays {
+                decayed.round() as usize
+            } else {
+                matched * 10
+            };
+            breakdown.gaps += 50usize.saturating_sub(gaps);
+        }
+    }
+
+    // 5. Acronym match (handles "tdd" -> "Test Driven Development")
+    if config.enable_acronym && query_chars >= 2 {
+        let _timer = profile::timer(Pass::Acronym);
+        let mut initials = config.tokenizer.word_initials(candidate);
+        let is_acronym = q.chars().all(|qc| {
+            initials
+                .next()
+                .is_some_and(|(_, ic)| ic.to_lowercase().eq(std::iter::once(qc)))
+        });
+        if is_acronym {
+            breakdown.acronym += 250;
+            // the initials replace the scattered subsequence alignment, but not a substring
+            if substring.is_empty()
+                && let Some(highlights) = highlights.as_deref_mut()
+            {
+                let initials = config.tokenizer.word_initials(candidate).take(query_chars);
+                highlights.match_indices = initials.map(|(i, _)| i).collect();
+                highlights.initials = true;
+            }
+        }
+    }
+
+    // 6. Edit distance bonus (handles "heyp" -> "hey"), skipped for long queries which are rarely
+    // that close to a whole candidate, while the distance costs the most for them
+    if edit_distance {
+        let _timer = profile::timer(Pass::EditDistance);
+        let dist = if ascii {
+            bounded_edit_distance(q.as_bytes(), c.as_bytes(), 2)
+        } else {
+            levenshtein_bounded(q, c, 2)
+        };
+        if let Some(dist) = dist {
+            breakdown.edit_distance += (3 - dist) * 30;
+        }
+    }
+
+    // A few query characters turning up in the candidate (e.g. the 'x' of "qqqqxzj" in "extra")
+    // aren't a match: without a pass that found the whole query, or a typo of it, nothing counts.
+    let is_match = whole_subsequence
+        || breakdown.substring > 0
+        || breakdown.prefix > 0
+        || breakdown.acronym > 0
+        || breakdown.edit_distance > 0;
+    if !is_match {
+        breakdown = ScoreBreakdown::default();
+    }
+
+    if let Some(highlights) = highlights {
+        if !is_match {
+            highlights.match_indices.clear();
+        }
+        highlights.match_indices.sort_unstable();
+        highlights.match_indices.dedup();
+    }
+
+    breakdown
+}
+
+// How candidates are split into words for the acronym pass.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tokenizer {
+    // prose, "Test Driven Development"
+    Whitespace,
+    // path components, "src/main.rs" is "src" and "main.rs"
+    Path,
+    // alphanumeric runs split on case changes, "parse_argsFromEnv" is "parse", "args", "From", "Env"
+    #[default]
+    Identifier,
+}
+
+impl FromStr for Tokenizer {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "whitespace" => Ok(Tokenizer::Whitespace),
+            "path" => Ok(Tokenizer::Path),
+            "identifier" => Ok(Tokenizer::Identifier),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Tokenizer {
+    fn is_separator(self, ch: char) -> bool {
+        match self {
+            Tokenizer::Whitespace => ch.is_whitespace(),
+            Tokenizer::Path => matches!(ch, '/' | '\\'),
+            Tokenizer::Identifier => !ch.is_alphanumeric(),
+        }
+    }
+
+    // the first character of every word, with its byte offset
+    pub fn word_initials(self, text: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+        let mut prev: Option<char> = None;
+        text.char_indices().filter(move |&(_, ch)| {
+            let starts_word = match prev {
+                None => true,
+                Some(p) => {
+                    self.is_separator(p)
+                        || (matches!(self, Tokenizer::Identifier)
+                            && p.is_lowercase()
+                            && ch.is_uppercase())
+                }
+            };
+            prev = Some(ch);
+            !self.is_separator(ch) && starts_word
+        })
+    }
+}
+
+// edit distance in chars, or None as soon as it's certain to be larger than max
+pub fn levenshtein_bounded(a: &str, b: &str, max: usize) -> Option<usize> {
+    if a.is_ascii() && b.is_ascii() {
+        return bounded_edit_distance(a.as_bytes(), b.as_bytes(), max);
+    }
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    bounded_edit_distance(&a, &b, max)
+}
+
+// the DP behind levenshtein_bounded, over bytes for ASCII text and chars otherwise
+fn bounded_edit_distance<T: PartialEq>(a: &[T], b: &[T], max: usize) -> Option<usize> {
+    // the distance is at least the length difference, so skip the DP when it can't be within max
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
     let mut costs: Vec<usize> = (0..=b.len()).collect();
 
-    for (i, ca) in a.chars().enumerate() {
+    for (i, ca) in a.iter().enumerate() {
         let mut last = i;
         costs[0] = i + 1;
+        let mut row_min = costs[0];
 
-        for (j, cb) in b.chars().enumerate() {
+        for (j, cb) in b.iter().enumerate() {
             let new = if ca == cb {
                 last
             } else {
@@ -102,22 +1164,799 @@ fn levenshtein(a: &str, b: &str) -> usize {
             };
             last = costs[j + 1];
             costs[j + 1] = new;
+            row_min = row_min.min(new);
+        }
+
+        // no later row can get below the minimum of this one
+        if row_min > max {
+            return None;
         }
     }
 
-    costs[b.len()]
+    let dist = costs[b.len()];
+    (dist <= max).then_some(dist)
+}
+
+fn similarity(query_embedding: &[f32], candidate_embedding: &[f32], normalized: bool) -> f32 {
+    let _timer = profile::timer(Pass::Cosine);
+    if normalized {
+        cosine_similarity(query_embedding, candidate_embedding)
+    } else {
+        cosine_similarity_full(query_embedding, candidate_embedding)
+    }
+}
+
+// the similarity on a 0 - max scale, negative ones are 0
+fn similarity_points(similarity: f32, max: usize) -> usize {
+    (similarity * max as f32) as usize
 }
 
 pub fn semantic_match(
     query: &str,
     candidate: &str,
-    query_embedding: &Vec<f32>,
-    candidate_embedding: &Vec<f32>,
+    prepared: &PreparedCandidate,
+    query_embedding: &[f32],
+    candidate_embedding: &[f32],
+    normalized: bool,
+    config: &ScoringConfig,
 ) -> Option<Suggestion> {
-    let f_match = fuzzy_match(query, candidate);
-    Some(Suggestion {
+    let similarity = similarity(query_embedding, candidate_embedding, normalized);
+    Some(semantic_suggestion(
+        query, candidate, prepared, similarity, config,
+    ))
+}
+
+// ranked by the similarity, highlighted where the query also matches lexically
+fn semantic_suggestion(
+    query: &str,
+    candidate: &str,
+    prepared: &PreparedCandidate,
+    similarity: f32,
+    config: &ScoringConfig,
+) -> Suggestion {
+    let f_match = fuzzy_match_prepared(query, candidate, prepared, config).unwrap_or_default();
+    Suggestion {
         text: candidate.to_string(),
-        match_indices: f_match.map_or(vec![], |m| m.match_indices),
-        score: (cosine_similarity(query_embedding, candidate_embedding) * 1000.0) as usize,
-    })
+        semantic_only: f_match.match_indices.is_empty(),
+        match_indices: f_match.match_indices,
+        score: similarity_points(similarity, MAX_SCORE),
+        unmatched_query_indices: f_match.unmatched_query_indices,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(query: &str, candidate: &str) -> usize {
+        fuzzy_match(query, candidate, &ScoringConfig::default()).map_or(0, |sug| sug.score)
+    }
+
+    #[test]
+    fn semantic_threshold_compares_the_unrounded_similarity() {
+        let query = [1.0, 0.0];
+        let similar = [0.4005, (1.0f32 - 0.4005 * 0.4005).sqrt()];
+        let embeddings = HashMap::from([("option".to_string(), similar.to_vec())]);
+        let config = ScoringConfig::default();
+        let scorer = |threshold| SemanticScorer {
+            query_embedding: &query,
+            option_embeddings: &embeddings,
+            normalized: true,
+            config: &config,
+            threshold,
+        };
+        let prepared = PreparedCandidate::new("option");
+        let passing = scorer(similarity(&query, &similar, true));
+        assert_eq!(passing.score_only("q", "option", &prepared), Some(400));
+        assert!(passing.score("q", "option").is_some());
+        assert!(passing.rank_score("q", "option", &prepared).is_some());
+        let failing = scorer(0.401);
+        assert_eq!(failing.score_only("q", "option", &prepared), None);
+        assert!(failing.score("q", "option").is_none());
+    }
+
+    #[test]
+    fn partial_alignment_is_no_match() {
+        assert_eq!(score("qqqqxzj", "extra"), 0);
+        let sug = fuzzy_match("qqqqxzj", "extra", &ScoringConfig::default()).unwrap();
+        assert!(sug.match_indices.is_empty());
+        // a typo or the whole query in order still matches
+        assert!(score("heyp", "hey") > 0);
+        assert!(score("helo", "hello") > 0);
+    }
+
+    #[test]
+    fn bounded_edit_distance_agrees_with_the_full_one() {
+        let pairs = [
+            ("kitten", "sitting"),
+            ("hey", "heyp"),
+            ("abc", "abc"),
+            ("", "ab"),
+        ];
+        for (a, b) in pairs {
+            let full = levenshtein_bounded(a, b, usize::MAX).unwrap();
+            for max in 0..4 {
+                let expected = (full <= max).then_some(full);
+                assert_eq!(levenshtein_bounded(a, b, max), expected, "{a} {b} {max}");
+            }
+        }
+        assert_eq!(levenshtein_bounded("naïve", "naive", 1), Some(1));
+        // the length difference alone rules out a long query against a short candidate
+        assert_eq!(levenshtein_bounded("abcdefghij", "abc", 2), None);
+        let without_edits = ScoringConfig {
+            enable_edit_distance: false,
+            ..ScoringConfig::default()
+        };
+        let long = fuzzy_match("abcdefghij", "abc", &without_edits).map(|sug| sug.score);
+        assert_eq!(Some(score("abcdefghij", "abc")), long);
+    }
+
+    #[test]
+    fn prepared_candidates_score_like_unprepared_ones() {
+        let slug = ScoringConfig {
+            slug: true,
+            ..ScoringConfig::default()
+        };
+        let folding = ScoringConfig {
+            case_folding: true,
+            ..ScoringConfig::default()
+        };
+        let cases: [(ScoringConfig, &[&str], &[&str]); 3] = [
+            (
+                ScoringConfig::default(),
+                &["Hello World", "cafe\u{301} au lait", "ÉCOLE", "src/main.rs"],
+                &["hel", "caf", "ecol", "école", "main", "hw"],
+            ),
+            (
+                slug,
+                &["Hello, World!", "Crème Brûlée", "Cafe\u{301} au lait"],
+                &["hello-world", "brulee", "cafe au", "xyz"],
+            ),
+            (
+                folding,
+                &["Straße", "İstanbul", "cafe\u{301} au lait", "Fuß"],
+                &["strasse", "istanbul", "café", "ss", "xyz"],
+            ),
+        ];
+        for (config, candidates, queries) in &cases {
+            for &candidate in *candidates {
+                let mut prepared = PreparedCandidate::new(candidate);
+                // only the form the config scores on is prepared
+                prepared.prepare_forms(candidate, config);
+                assert_eq!(prepared.slug.is_some(), config.slug);
+                assert_eq!(prepared.folded.is_some(), config.case_folding);
+                for &query in *queries {
+                    let fly = fuzzy_match(query, candidate, config).unwrap();
+                    let once = fuzzy_match_prepared(query, candidate, &prepared, config).unwrap();
+                    assert_eq!(fly.score, once.score, "{query} {candidate}");
+                    assert_eq!(fly.match_indices, once.match_indices, "{query} {candidate}");
+                    let score = fuzzy_score_prepared(query, candidate, &prepared, config);
+                    assert_eq!(score, Some(fly.score), "{query} {candidate}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn score_without_highlights_is_the_match_score() {
+        let decayed = ScoringConfig {
+            position_decay: 0.5,
+            prefer_end: true,
+            ..ScoringConfig::default()
+        };
+        for config in [ScoringConfig::default(), decayed] {
+            for (query, candidate) in [
+                ("app", "apple"),
+                ("tdd", "Test Driven Development"),
+                ("heyp", "hey"),
+                ("zz", "apple"),
+                ("main", "src/main.rs"),
+            ] {
+                let prepared = PreparedCandidate::new(candidate);
+                let sug = fuzzy_match_prepared(query, candidate, &prepared, &config).unwrap();
+                let score = fuzzy_score_prepared(query, candidate, &prepared, &config);
+                assert_eq!(score, Some(sug.score), "{query} {candidate}");
+            }
+        }
+    }
+
+    #[test]
+    fn ascii_fast_path_matches_the_general_one() {
+        let folding = ScoringConfig {
+            case_folding: true,
+            ..ScoringConfig::default()
+        };
+        for config in [ScoringConfig::default(), folding] {
+            for candidate in ["Hello World", "get_user_name", "src/main.rs", "abc"] {
+                let fast = PreparedCandidate::new(candidate);
+                assert!(fast.ascii);
+                let general = PreparedCandidate {
+                    ascii: false,
+                    ..fast.clone()
+                };
+                for query in ["hello", "gun", "mian", "abd", "x", "abcd"] {
+                    let fast = fuzzy_match_prepared(query, candidate, &fast, &config).unwrap();
+                    let general =
+                        fuzzy_match_prepared(query, candidate, &general, &config).unwrap();
+                    assert_eq!(fast.score, general.score, "{query} {candidate}");
+                    assert_eq!(
+                        fast.match_indices, general.match_indices,
+                        "{query} {candidate}"
+                    );
+                    assert_eq!(
+                        fast.unmatched_query_indices,
+                        general.unmatched_query_indices
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn edit_distance_is_skipped_for_long_queries() {
+        let config = ScoringConfig::default();
+        assert_eq!(explain("heyp", "hey", &config).edit_distance, 60);
+        assert_eq!(
+            explain("internatinal", "international", &config).edit_distance,
+            0
+        );
+        let longer = ScoringConfig {
+            edit_distance_max_query: 20,
+            ..ScoringConfig::default()
+        };
+        assert_eq!(
+            explain("internatinal", "international", &longer).edit_distance,
+            60
+        );
+    }
+
+    #[test]
+    fn candidates_missing_query_chars_skip_the_subsequence_and_edit_distance() {
+        let config = ScoringConfig::default();
+        let is_subsequence = |q: &str, c: &str| {
+            let mut chars = c.chars();
+            q.chars().all(|qc| chars.any(|cc| cc == qc))
+        };
+        // more than two missing chars: neither a subsequence nor within the edit distance
+        for (query, candidate) in [
+            ("qqqqxzj", "extra"),
+            ("abcdefghijklmnop", "a bit of everything"),
+        ] {
+            assert!(missing_chars(query, candidate, true) > 2);
+            assert!(!is_subsequence(query, candidate));
+            assert!(levenshtein_bounded(query, candidate, usize::MAX).unwrap() > 2);
+            assert_eq!(
+                explain(query, candidate, &config).total(),
+                0,
+                "{query} {candidate}"
+            );
+        }
+        // one missing char still gets the edit distance bonus
+        assert_eq!(missing_chars("café", "cafe", false), 1);
+        assert!(!is_subsequence("café", "cafe"));
+        assert_eq!(explain("café", "cafe", &config).edit_distance, 60);
+        // the highlights still get the partial alignment
+        let sug = fuzzy_match("qqqqxzj", "extra", &config).unwrap();
+        assert_eq!(sug.unmatched_query_indices, [0, 1, 2, 3, 5, 6]);
+    }
+
+    #[test]
+    fn the_missing_chars_gate_keeps_the_scores() {
+        let config = ScoringConfig::default();
+        for (query, candidate) in [
+            ("qqqqxzj", "extra"),
+            ("café", "cafe"),
+            ("helo", "hello world"),
+            ("quickly jumping foxes", "quick brown fox jumps"),
+        ] {
+            assert_eq!(
+                explain(query, candidate, &config),
+                explain_ungated(
+                    query,
+                    candidate,
+                    &PreparedCandidate::new(candidate),
+                    &config
+                ),
+                "{query} {candidate}"
+            );
+        }
+    }
+
+    #[test]
+    fn repeated_characters_are_counted_once() {
+        let config = ScoringConfig::default();
+        for (query, candidate) in [("abc", "abcabc"), ("aa", "aaaa"), ("ll", "hell wall")] {
+            let breakdown = explain(query, candidate, &config);
+            // the subsequence counts the substring's chars, not their repeats further on
+            assert_eq!(
+                breakdown.subsequence,
+                query.len() * 10,
+                "{query} {candidate}"
+            );
+            let sug = fuzzy_match(query, candidate, &config).unwrap();
+            assert_eq!(sug.match_indices.len(), query.len(), "{query} {candidate}");
+        }
+        assert_eq!(score("ll", "hell wall"), score("ll", "hello"));
+        assert!(score("abc", "abcabc") <= score("abc", "abcd"));
+    }
+
+    #[test]
+    fn composite_score_is_the_weighted_blend() {
+        let config = ScoringConfig::default();
+        let composite = CompositeScorer::new(vec![
+            (Box::new(FuzzyScorer { config: &config }), 0.7),
+            (Box::new(EditDistanceScorer::new("helo", 2)), 0.3),
+        ]);
+        let fuzzy = score("helo", "hello") as f32 / MAX_SCORE as f32;
+        // one edit of at most two, in whole points
+        let edits = 0.666;
+        let expected = ((0.7 * fuzzy + 0.3 * edits) * MAX_SCORE as f32).round() as usize;
+        let sug = composite.score("helo", "hello").unwrap();
+        assert_eq!(sug.score, expected);
+        assert_eq!(sug.match_indices, [0, 1, 3, 4]);
+        let prepared = PreparedCandidate::new("hello");
+        assert_eq!(
+            composite.score_only("helo", "hello", &prepared),
+            Some(expected)
+        );
+        let rank = composite.rank_score("helo", "hello", &prepared).unwrap();
+        assert_eq!((rank as f32 / RANK_SCALE as f32).round() as usize, expected);
+        // the edit distance scorer doesn't match it, so neither does the blend
+        assert!(composite.score("helo", "hello world").is_none());
+    }
+
+    #[test]
+    fn edit_distance_scorer_normalizes_other_queries_too() {
+        let scorer = EditDistanceScorer::new("cafe\u{301}", 1);
+        let prepared = PreparedCandidate::new("Café");
+        let expected = scorer.score_only("cafe\u{301}", "Café", &prepared);
+        assert_eq!(expected, Some(MAX_SCORE));
+        // not the query it was made for, so normalized when scored
+        assert_eq!(scorer.score_only("CAFÉ", "Café", &prepared), expected);
+        assert!(scorer.score_only("tea", "Café", &prepared).is_none());
+    }
+
+    #[test]
+    fn a_semantic_threshold_drops_exact_lexical_matches() {
+        let config = ScoringConfig::default();
+        let query = [1.0, 0.0];
+        let embeddings = HashMap::from([
+            ("apple".to_string(), vec![0.0, 1.0]),
+            ("banana".to_string(), vec![1.0, 0.0]),
+        ]);
+        let composite = CompositeScorer::new(vec![
+            (Box::new(FuzzyScorer { config: &config }), 0.5),
+            (
+                Box::new(SemanticScorer {
+                    query_embedding: &query,
+                    option_embeddings: &embeddings,
+                    normalized: true,
+                    config: &config,
+                    threshold: 0.5,
+                }),
+                0.5,
+            ),
+        ]);
+        // the exact lexical match is below the semantic threshold
+        assert!(composite.score("apple", "apple").is_none());
+        let prepared = PreparedCandidate::new("apple");
+        assert!(composite.score_only("apple", "apple", &prepared).is_none());
+        // no lexical match adds 0 and keeps the semantic half
+        assert_eq!(score("apple", "banana"), 0);
+        let sug = composite.score("apple", "banana").unwrap();
+        assert_eq!(sug.score, MAX_SCORE / 2);
+        let prepared = PreparedCandidate::new("banana");
+        assert_eq!(
+            composite.score_only("apple", "banana", &prepared),
+            Some(MAX_SCORE / 2)
+        );
+    }
+
+    #[test]
+    fn zero_weight_scorers_are_left_out() {
+        let config = ScoringConfig::default();
+        let composite = CompositeScorer::new(vec![
+            (Box::new(FuzzyScorer { config: &config }), 1.0),
+            (Box::new(EditDistanceScorer::new("helo", 2)), 0.0),
+        ]);
+        // the edit distance scorer would filter it, so it isn't run
+        let sug = composite.score("helo", "hello world").unwrap();
+        assert_eq!(sug.score, score("helo", "hello world"));
+        let prepared = PreparedCandidate::new("hello world");
+        assert!(
+            composite
+                .rank_score("helo", "hello world", &prepared)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn disabled_passes_add_nothing() {
+        let all = ScoringConfig::default();
+        let full = explain("hel", "help", &all);
+        assert!(full.prefix > 0 && full.subsequence > 0 && full.edit_distance > 0);
+        let without = |config: ScoringConfig| explain("hel", "help", &config);
+        let no_prefix = without(ScoringConfig {
+            enable_prefix: false,
+            ..ScoringConfig::default()
+        });
+        assert_eq!(no_prefix.total(), full.total() - full.prefix);
+        let no_edits = without(ScoringConfig {
+            enable_edit_distance: false,
+            ..ScoringConfig::default()
+        });
+        assert_eq!(no_edits.total(), full.total() - full.edit_distance);
+        let no_subsequence = without(ScoringConfig {
+            enable_subsequence: false,
+            ..ScoringConfig::default()
+        });
+        assert_eq!(no_subsequence.subsequence + no_subsequence.gaps, 0);
+        assert_eq!(
+            no_subsequence.total(),
+            full.total() - full.subsequence - full.gaps
+        );
+
+        let substring = explain("elp", "help", &all);
+        let no_substring = explain(
+            "elp",
+            "help",
+            &ScoringConfig {
+                enable_substring: false,
+                ..ScoringConfig::default()
+            },
+        );
+        assert!(substring.substring > 0);
+        assert_eq!(no_substring.substring, 0);
+        assert!(no_substring.total() < substring.total());
+
+        let acronym = explain("tdd", "Test Driven Development", &all).acronym;
+        let no_acronym = ScoringConfig {
+            enable_acronym: false,
+            ..ScoringConfig::default()
+        };
+        assert_eq!(acronym, 250);
+        assert_eq!(
+            explain("tdd", "Test Driven Development", &no_acronym).acronym,
+            0
+        );
+    }
+
+    #[test]
+    fn full_cosine_divides_by_the_norms() {
+        let a = [3.0f32, 4.0];
+        let b = [4.0f32, 3.0];
+        // the dot product alone overshoots for vectors longer than 1
+        assert_eq!(cosine_similarity(&a, &b), 24.0);
+        assert!((cosine_similarity_full(&a, &b) - 0.96).abs() < 1e-6);
+        let unit = normalized(&[a.to_vec(), b.to_vec()]);
+        assert!(
+            (cosine_similarity(&unit[0], &unit[1]) - cosine_similarity_full(&a, &b)).abs() < 1e-6
+        );
+        assert_eq!(cosine_similarity_full(&a, &[0.0, 0.0]), 0.0);
+        assert!(embeddings_normalized(&unit));
+        assert!(!embeddings_normalized(&[a.to_vec()]));
+    }
+
+    #[test]
+    fn unmatched_query_characters_are_reported() {
+        let config = ScoringConfig::default();
+        let sug = fuzzy_match("hexlo", "hello", &config).unwrap();
+        assert!(sug.score > 0);
+        assert_eq!(sug.unmatched_query_indices, [2]);
+        // char positions, not bytes
+        let sug = fuzzy_match("çafé", "cafe", &config).unwrap();
+        assert_eq!(sug.unmatched_query_indices, [0, 3]);
+        assert!(
+            fuzzy_match("hel", "hello", &config)
+                .unwrap()
+                .unmatched_query_indices
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn prefer_end_ranks_filename_matches_above_directory_ones() {
+        let paths = ["main_util/something.rs", "src/main.rs"];
+        let by_score = |config: &ScoringConfig| {
+            paths
+                .into_iter()
+                .max_by_key(|path| fuzzy_match("main", path, config).map_or(0, |sug| sug.score))
+                .unwrap()
+        };
+        assert_eq!(
+            by_score(&ScoringConfig::default()),
+            "main_util/something.rs"
+        );
+        let config = ScoringConfig {
+            prefer_end: true,
+            ..ScoringConfig::default()
+        };
+        assert_eq!(by_score(&config), "src/main.rs");
+    }
+
+    #[test]
+    fn acronyms_match_the_initials_of_each_word() {
+        let config = ScoringConfig::default();
+        for (candidate, initials) in [
+            ("Test Driven Development", [0, 5, 12]),
+            ("test_driven_development", [0, 5, 12]),
+            ("testDrivenDevelopment", [0, 4, 10]),
+        ] {
+            assert_eq!(
+                explain("tdd", candidate, &config).acronym,
+                250,
+                "{candidate}"
+            );
+            let sug = fuzzy_match("tdd", candidate, &config).unwrap();
+            assert_eq!(sug.match_indices, initials, "{candidate}");
+        }
+        assert_eq!(explain("tdd", "toddler", &config).acronym, 0);
+        let config = ScoringConfig {
+            enable_acronym: false,
+            ..ScoringConfig::default()
+        };
+        assert_eq!(explain("tdd", "testDrivenDevelopment", &config).acronym, 0);
+    }
+
+    #[test]
+    fn case_folding_equates_sharp_s_with_ss() {
+        let folding = ScoringConfig {
+            case_folding: true,
+            ..ScoringConfig::default()
+        };
+        assert_eq!(score("strasse", "Straße"), 128);
+        let sug = fuzzy_match("strasse", "Straße", &folding).unwrap();
+        assert_eq!(sug.score, MAX_SCORE);
+        // both "s" are the one "ß"
+        assert_eq!(sug.match_indices, [0, 1, 2, 3, 4, 6]);
+        assert_eq!(score("ss", "Fuß"), 0);
+        assert_eq!(
+            fuzzy_match("ss", "Fuß", &folding).unwrap().match_indices,
+            [2]
+        );
+    }
+
+    #[test]
+    fn dotted_and_dotless_i_keep_their_highlights() {
+        // "İ" lowercases and folds to "i" and a combining dot, the dotless "ı" stays itself
+        for config in [
+            ScoringConfig::default(),
+            ScoringConfig {
+                case_folding: true,
+                ..ScoringConfig::default()
+            },
+        ] {
+            let sug = fuzzy_match("istanbul", "İstanbul", &config).unwrap();
+            assert_eq!(sug.match_indices, [0, 2, 3, 4, 5, 6, 7, 8]);
+            let sug = fuzzy_match("kırmızı", "KIRMIZI", &config).unwrap();
+            assert_eq!(sug.score, 0);
+        }
+    }
+
+    #[test]
+    fn only_one_alignment_is_highlighted() {
+        let config = ScoringConfig::default();
+        let indices = |query, candidate| {
+            fuzzy_match(query, candidate, &config)
+                .unwrap()
+                .match_indices
+        };
+        // the query twice, only the first occurrence
+        assert_eq!(indices("foo", "foo bar foo"), [0, 1, 2]);
+        // the substring rather than the scattered subsequence before it
+        assert_eq!(indices("ab", "a_x_b ab"), [6, 7]);
+        // without a substring, the tightest subsequence rather than the leftmost
+        assert_eq!(indices("lt", "large lot"), [6, 8]);
+    }
+
+    #[test]
+    fn normalized_copies_leave_the_originals_and_zero_non_finite_values() {
+        let originals = vec![vec![3.0f32, 4.0], vec![0.0, 0.0]];
+        let copies = normalized(&originals);
+        assert_eq!(originals[0], [3.0, 4.0]);
+        assert_eq!(copies, [vec![0.6, 0.8], vec![0.0, 0.0]]);
+        let mut embeddings = vec![
+            vec![f32::NAN, 3.0, 4.0],
+            vec![f32::INFINITY, f32::NEG_INFINITY],
+        ];
+        normalize_embeddings(&mut embeddings);
+        assert_eq!(embeddings, [vec![0.0, 0.6, 0.8], vec![0.0, 0.0]]);
+    }
+
+    #[test]
+    fn repeated_query_chars_take_the_closest_pair() {
+        let config = ScoringConfig::default();
+        let indices = |candidate| fuzzy_match("ll", candidate, &config).unwrap().match_indices;
+        assert_eq!(indices("llama"), [0, 1]);
+        // not the "l" of "well" with the one of "oiled"
+        assert_eq!(indices("well oiled"), [2, 3]);
+        assert_eq!(indices("lamp oil"), [0, 7]);
+        assert!(score("ll", "llama") > score("ll", "well oiled"));
+        assert!(score("ll", "well oiled") > score("ll", "lamp oil"));
+        // the repeated "l" of a substring rather than the first "l"s of the candidate
+        assert_eq!(
+            fuzzy_match("lol", "all lol", &config)
+                .unwrap()
+                .match_indices,
+            [4, 5, 6]
+        );
+    }
+
+    #[test]
+    fn tokenizers_split_words_their_own_way() {
+        let initials = |tokenizer: Tokenizer, text| -> String {
+            tokenizer.word_initials(text).map(|(_, ch)| ch).collect()
+        };
+        let path = "src/file_manager/readDir.rs";
+        assert_eq!(
+            initials(Tokenizer::Whitespace, "Test Driven  Development"),
+            "TDD"
+        );
+        assert_eq!(initials(Tokenizer::Whitespace, path), "s");
+        assert_eq!(initials(Tokenizer::Path, path), "sfr");
+        assert_eq!(initials(Tokenizer::Identifier, path), "sfmrDr");
+        assert_eq!(initials(Tokenizer::Identifier, "parse_argsFromEnv"), "paFE");
+        // the byte offsets of the initials
+        let offsets: Vec<usize> = Tokenizer::Path
+            .word_initials("ä/ö")
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(offsets, [0, 3]);
+        assert!(matches!("path".parse(), Ok(Tokenizer::Path)));
+        assert!("words".parse::<Tokenizer>().is_err());
+    }
+
+    #[test]
+    fn explained_passes_sum_to_the_score() {
+        let config = ScoringConfig::default();
+        for (query, candidate) in [
+            ("hello", "hello"),
+            ("hel", "hello"),
+            ("helo", "hello world"),
+            ("tdd", "test_driven_development"),
+            ("xyz", "hello"),
+        ] {
+            let breakdown = explain(query, candidate, &config);
+            let sum = breakdown.exact
+                + breakdown.substring
+                + breakdown.prefix
+                + breakdown.subsequence
+                + breakdown.gaps
+                + breakdown.acronym
+                + breakdown.edit_distance;
+            assert_eq!(breakdown.total(), sum.min(MAX_SCORE), "{query} {candidate}");
+            assert_eq!(
+                breakdown.total(),
+                score(query, candidate),
+                "{query} {candidate}"
+            );
+        }
+        // the exact match is all there is
+        let exact = explain("hello", "hello", &config);
+        assert_eq!(
+            (exact.exact, exact.substring, exact.total()),
+            (MAX_SCORE, 0, MAX_SCORE)
+        );
+    }
+
+    #[test]
+    fn precomposed_and_decomposed_accents_match_alike() {
+        let precomposed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        let config = ScoringConfig::default();
+        for (query, candidate) in [(precomposed, decomposed), (decomposed, precomposed)] {
+            let sug = fuzzy_match(query, candidate, &config).unwrap();
+            assert_eq!(sug.score, MAX_SCORE, "{query:?} {candidate:?}");
+            // the original text is kept for display
+            assert_eq!(sug.text, candidate);
+        }
+        // the "e" and its accent are highlighted together
+        let sug = fuzzy_match(precomposed, decomposed, &config).unwrap();
+        assert_eq!(sug.match_indices, [0, 1, 2, 3, 4]);
+        let sug = fuzzy_match("f\u{e9}", "caf\u{e9}s cafe\u{301}", &config).unwrap();
+        assert_eq!(sug.match_indices, [2, 3]);
+    }
+
+    #[test]
+    fn acronym_highlights_point_at_the_original_initials() {
+        for case_folding in [false, true] {
+            let config = ScoringConfig {
+                case_folding,
+                ..ScoringConfig::default()
+            };
+            for query in ["tdd", "TDD"] {
+                let candidate = "TestDrivenDevelopment";
+                let sug = fuzzy_match(query, candidate, &config).unwrap();
+                let chars: Vec<char> = candidate.chars().collect();
+                let highlighted: String = sug.match_indices.iter().map(|&i| chars[i]).collect();
+                assert_eq!(highlighted, "TDD", "{query} {case_folding}");
+            }
+        }
+    }
+
+    #[test]
+    fn slugs_lowercase_strip_accents_and_join_the_words() {
+        for (text, expected) in [
+            ("Hello, World!", "hello-world"),
+            ("  Crème Brûlée  ", "creme-brulee"),
+            // a decomposed accent is stripped too, not a separator
+            ("Cafe\u{301} au lait", "cafe-au-lait"),
+            ("snake_case--and.dots", "snake-case-and-dots"),
+            ("Ünïcödé 2024", "unicode-2024"),
+        ] {
+            assert_eq!(slug(text).0, expected, "{text}");
+        }
+        let (slug, offsets) = slug("A, é");
+        assert_eq!(slug, "a-e");
+        // the '-' points at the ',' starting its run, the "e" at the whole "é"
+        assert_eq!(offsets, [0, 1, 3]);
+    }
+
+    #[test]
+    fn slug_matches_highlight_the_original_characters() {
+        let config = ScoringConfig {
+            slug: true,
+            ..ScoringConfig::default()
+        };
+        for query in ["hello-world", "helloworld", "Hello World"] {
+            let sug = fuzzy_match(query, "Hello, World!", &config).unwrap();
+            let highlighted: String = sug
+                .match_indices
+                .iter()
+                .map(|&i| &sug.text[i..i + 1])
+                .collect();
+            assert_eq!(highlighted, "HelloWorld", "{query}");
+        }
+        let sug = fuzzy_match("brulee", "Crème Brûlée", &config).unwrap();
+        let highlighted: String = sug
+            .match_indices
+            .iter()
+            .map(|&i| sug.text[i..].chars().next().unwrap())
+            .collect();
+        assert_eq!(highlighted, "Brûlée");
+        // without the slugs the punctuation makes it an approximate match at best
+        let score =
+            |config| fuzzy_match("hello-world", "Hello, World!", config).map(|sug| sug.score);
+        assert!(score(&ScoringConfig::default()).unwrap_or(0) < score(&config).unwrap());
+    }
+
+    #[test]
+    fn compound_emoji_are_highlighted_as_a_unit() {
+        // a thumbs up with a skin tone, then a flag of two regional indicators
+        let text = "\u{1f44d}\u{1f3fd} \u{1f1f3}\u{1f1f1}";
+        assert_eq!(whole_graphemes(text, &[0]), [0, 4]);
+        assert_eq!(whole_graphemes(text, &[13]), [9, 13]);
+        assert_eq!(whole_graphemes(text, &[4, 9]), [0, 4, 9, 13]);
+        let candidate = "\u{1f44d}\u{1f3fd} thumbs up";
+        let sug = fuzzy_match("\u{1f44d} thumbs", candidate, &ScoringConfig::default()).unwrap();
+        let highlighted: String = sug
+            .match_indices
+            .iter()
+            .map(|&i| candidate[i..].chars().next().unwrap())
+            .collect();
+        assert_eq!(highlighted, "\u{1f44d}\u{1f3fd} thumbs");
+    }
+
+    #[test]
+    fn position_decay_prefers_earlier_matches() {
+        let plain = ScoringConfig::default();
+        let decayed = ScoringConfig {
+            position_decay: 0.5,
+            ..ScoringConfig::default()
+        };
+        let candidates = ["config", "reconfigure", "the big config"];
+        let subsequence = |config| candidates.map(|c| explain("con", c, config).subsequence);
+        // without the decay a matched character is worth the same anywhere
+        assert_eq!(subsequence(&plain), [30, 30, 30]);
+        let decayed_points = subsequence(&decayed);
+        assert!(decayed_points[0] > decayed_points[1] && decayed_points[1] > decayed_points[2]);
+
+        let score = |candidate, config| fuzzy_match("con", candidate, config).unwrap().score;
+        assert!(score("config", &decayed) > score("reconfigure", &decayed));
+        // the later the match the more it loses, beyond the substring position bonus
+        let plain_drop = score("reconfigure", &plain) - score("the big config", &plain);
+        let decayed_drop = score("reconfigure", &decayed) - score("the big config", &decayed);
+        assert!(decayed_drop > plain_drop);
+    }
 }
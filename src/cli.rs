@@ -0,0 +1,444 @@
+use crate::draw::QueryStyle;
+use crate::embedder::{EmbedOrder, ModelSettings};
+use crate::structs::{BarScale, InputFormat, ScoringConfig, SortOrder};
+
+pub struct Args {
+    pub version: bool,
+    pub generate_embeddings: bool,
+    // number of options embedded (and held in memory) at once when generating embeddings
+    pub batch_size: usize,
+    // the order the options are embedded and written in
+    pub embed_order: EmbedOrder,
+    pub model_settings: ModelSettings,
+    pub semantic: bool,
+    // share of the semantic score in the blended ranking, the rest is fuzzy
+    pub semantic_weight: f32,
+    // minimum cosine similarity for an option to be shown in semantic mode
+    pub semantic_threshold: f32,
+    // rank only this many of the best lexical matches semantically, embedding them when needed
+    pub semantic_rerank: Option<usize>,
+    pub browse: bool,
+    // extra tab separated fields on each option line
+    pub input_format: InputFormat,
+    pub scoring_config: ScoringConfig,
+    // color query characters that don't match the top suggestion
+    pub show_unmatched: bool,
+    // how the typed query is drawn in the header
+    pub query_style: QueryStyle,
+    // event poll interval of the main loop, 0 blocks until input arrives
+    pub poll_ms: u64,
+    pub match_suffix: bool,
+    // maximum number of suggestions shown, None fits them to the terminal height
+    pub limit: Option<usize>,
+    // where Ctrl+S writes the current results
+    pub export_path: String,
+    pub regex: Option<String>,
+    // only options matching this glob, e.g. *.rs, are ranked
+    pub glob: Option<String>,
+    pub min_len: Option<usize>,
+    pub max_len: Option<usize>,
+    pub max_scan: Option<usize>,
+    // stop scoring after this many milliseconds and show the best results found so far
+    pub query_timeout_ms: Option<u64>,
+    // shown when a query matches nothing, None to show nothing
+    pub hint: Option<String>,
+    // only return options within this many edits of the query, ranked by distance
+    pub max_edit_distance: Option<usize>,
+    // print the time spent in each scoring pass to stderr on exit
+    pub profile: bool,
+    // no informational messages on stderr, warnings and errors are still printed
+    pub quiet: bool,
+    // appends the latency of every search to this file
+    pub timing_log: Option<String>,
+    // use the files below this directory as options instead of the word list
+    pub dir: Option<String>,
+    // with dir, also list files excluded by .gitignore and hidden files
+    pub no_ignore: bool,
+    // leave out the score bar, giving its width to the suggestions
+    pub no_bar: bool,
+    pub bar_scale: BarScale,
+    // mark suggestions that were ranked semantically but don't match the query lexically
+    pub mark_semantic: bool,
+    // boost options that were recently and often selected with Enter
+    pub frecency: bool,
+    // rank numbers before the suggestions, Alt+digit selects by rank
+    pub numbered: bool,
+    // draw at most this many chars of each label, cutting long ones at the start
+    pub truncate_left: Option<usize>,
+    // the best matches listed by score or alphabetically
+    pub sort: SortOrder,
+    pub reverse: bool,
+    // group exact, prefix and substring matches above the other matches
+    pub tiers: bool,
+    // favorites listed first when they match, --pin can be given several times
+    pub pins: Vec<String>,
+    // drop the matches after the first score drop of more than this fraction
+    pub cutoff_gap: Option<f32>,
+    // run a single search for this query and print the results instead of starting the interface
+    pub query: Option<String>,
+    pub json: bool,
+    // NUL separated texts instead of lines
+    pub print0: bool,
+    // only the texts, without scores or colors
+    pub plain: bool,
+    // the options file, words.txt by default; can be a FIFO
+    pub words: Option<String>,
+    // where Enter writes the selected suggestion; can be a FIFO
+    pub output: Option<String>,
+    // shell command run with the selected suggestion, its output is shown below the suggestions
+    pub preview: Option<String>,
+    pub preview_lines: usize,
+    // print what each scoring pass gives this query and candidate
+    pub explain: Option<(String, String)>,
+    // print the stored embedding of this word and its nearest neighbors
+    pub inspect: Option<String>,
+    // print the k most similar words to this one
+    pub neighbors: Option<String>,
+    pub k: usize,
+}
+
+// number of results without --limit when there is no terminal to fit them to
+pub const DEFAULT_LIMIT: usize = 20;
+
+pub const DEFAULT_HINT: &str = "No matches: Backspace to edit, Ctrl+U to clear, Esc to quit";
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            version: false,
+            generate_embeddings: false,
+            batch_size: 256,
+            embed_order: EmbedOrder::File,
+            model_settings: ModelSettings::default(),
+            semantic: false,
+            semantic_weight: 1.0,
+            semantic_threshold: 0.0,
+            semantic_rerank: None,
+            browse: false,
+            input_format: InputFormat::default(),
+            scoring_config: ScoringConfig::default(),
+            show_unmatched: false,
+            query_style: QueryStyle::Bold,
+            poll_ms: 10,
+            match_suffix: false,
+            limit: None,
+            export_path: "fuzzyq_results.txt".to_string(),
+            regex: None,
+            glob: None,
+            min_len: None,
+            max_len: None,
+            max_scan: None,
+            query_timeout_ms: None,
+            hint: Some(DEFAULT_HINT.to_string()),
+            max_edit_distance: None,
+            profile: false,
+            quiet: false,
+            timing_log: None,
+            dir: None,
+            no_ignore: false,
+            no_bar: false,
+            bar_scale: BarScale::Spread,
+            mark_semantic: false,
+            frecency: false,
+            numbered: false,
+            truncate_left: None,
+            sort: SortOrder::Score,
+            reverse: false,
+            tiers: false,
+            pins: Vec::new(),
+            cutoff_gap: None,
+            query: None,
+            json: false,
+            print0: false,
+            plain: false,
+            words: None,
+            output: None,
+            preview: None,
+            preview_lines: 10,
+            explain: None,
+            inspect: None,
+            neighbors: None,
+            k: 10,
+        }
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} expects a value", flag))?;
+    value
+        .parse::<T>()
+        .map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+// flags override the given defaults, which normally come from the config file
+pub fn parse_args(mut args: impl Iterator<Item = String>, defaults: Args) -> Result<Args, String> {
+    let mut parsed = defaults;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--version" => parsed.version = true,
+            "--generate-embeddings" => parsed.generate_embeddings = true,
+            "--batch-size" => parsed.batch_size = parse_value(&arg, args.next())?,
+            "--embed-order" => parsed.embed_order = parse_value(&arg, args.next())?,
+            "--max-length" => {
+                parsed.model_settings.max_length = Some(parse_value(&arg, args.next())?);
+            }
+            "--threads" => parsed.model_settings.threads = Some(parse_value(&arg, args.next())?),
+            "--semantic" => parsed.semantic = true,
+            "--semantic-weight" => parsed.semantic_weight = parse_value(&arg, args.next())?,
+            "--semantic-threshold" => {
+                parsed.semantic_threshold = parse_value(&arg, args.next())?;
+            }
+            "--semantic-rerank" => {
+                parsed.semantic_rerank = Some(parse_value(&arg, args.next())?);
+            }
+            "--browse" => parsed.browse = true,
+            "--icons" => parsed.input_format.icons = true,
+            "--descriptions" => parsed.input_format.descriptions = true,
+            "--keywords" => parsed.input_format.keywords = true,
+            "--raw" => parsed.input_format.raw = true,
+            "--columns" => parsed.input_format.columns = true,
+            "--no-substring" => parsed.scoring_config.enable_substring = false,
+            "--no-prefix" => parsed.scoring_config.enable_prefix = false,
+            "--no-subsequence" => parsed.scoring_config.enable_subsequence = false,
+            "--no-edit-distance" => parsed.scoring_config.enable_edit_distance = false,
+            "--edit-distance-max-query" => {
+                parsed.scoring_config.edit_distance_max_query = parse_value(&arg, args.next())?;
+            }
+            "--no-acronym" => parsed.scoring_config.enable_acronym = false,
+            "--prefer-end" => parsed.scoring_config.prefer_end = true,
+            "--position-decay" => {
+                parsed.scoring_config.position_decay = parse_value(&arg, args.next())?;
+            }
+            "--case-folding" => parsed.scoring_config.case_folding = true,
+            "--slug" => parsed.scoring_config.slug = true,
+            "--tokenizer" => parsed.scoring_config.tokenizer = parse_value(&arg, args.next())?,
+            "--show-unmatched" => parsed.show_unmatched = true,
+            "--query-style" => parsed.query_style = parse_value(&arg, args.next())?,
+            "--poll-ms" => parsed.poll_ms = parse_value(&arg, args.next())?,
+            "--match-suffix" => parsed.match_suffix = true,
+            "--limit" => parsed.limit = Some(parse_value(&arg, args.next())?),
+            "--export-path" => parsed.export_path = parse_value(&arg, args.next())?,
+            "--regex" => parsed.regex = Some(parse_value(&arg, args.next())?),
+            "--glob" => parsed.glob = Some(parse_value(&arg, args.next())?),
+            "--min-len" => parsed.min_len = Some(parse_value(&arg, args.next())?),
+            "--max-len" => parsed.max_len = Some(parse_value(&arg, args.next())?),
+            "--max-scan" => parsed.max_scan = Some(parse_value(&arg, args.next())?),
+            "--query-timeout-ms" => {
+                parsed.query_timeout_ms = Some(parse_value(&arg, args.next())?);
+            }
+            "--hint" => parsed.hint = Some(parse_value(&arg, args.next())?),
+            "--no-hint" => parsed.hint = None,
+            "--profile" => parsed.profile = true,
+            "--quiet" => parsed.quiet = true,
+            "--timing-log" => parsed.timing_log = Some(parse_value(&arg, args.next())?),
+            "--dir" => parsed.dir = Some(parse_value(&arg, args.next())?),
+            "--no-ignore" => parsed.no_ignore = true,
+            "--no-bar" => parsed.no_bar = true,
+            "--bar-scale" => parsed.bar_scale = parse_value(&arg, args.next())?,
+            "--mark-semantic" => parsed.mark_semantic = true,
+            "--frecency" => parsed.frecency = true,
+            "--numbered" => parsed.numbered = true,
+            "--truncate-left" => parsed.truncate_left = Some(parse_value(&arg, args.next())?),
+            "--sort" => parsed.sort = parse_value(&arg, args.next())?,
+            "--reverse" => parsed.reverse = true,
+            "--tiers" => parsed.tiers = true,
+            // added to the pins of the config file rather than replacing them
+            "--pin" => parsed.pins.push(parse_value(&arg, args.next())?),
+            "--cutoff-gap" => parsed.cutoff_gap = Some(parse_value(&arg, args.next())?),
+            "--max-edit-distance" => {
+                parsed.max_edit_distance = Some(parse_value(&arg, args.next())?);
+            }
+            "--query" => parsed.query = Some(parse_value(&arg, args.next())?),
+            "--json" => parsed.json = true,
+            "--print0" => parsed.print0 = true,
+            "--plain" => parsed.plain = true,
+            "--words" => parsed.words = Some(parse_value(&arg, args.next())?),
+            "--output" => parsed.output = Some(parse_value(&arg, args.next())?),
+            "--preview" => parsed.preview = Some(parse_value(&arg, args.next())?),
+            "--preview-lines" => parsed.preview_lines = parse_value(&arg, args.next())?,
+            "--explain" => {
+                let query = parse_value(&arg, args.next())?;
+                let candidate = parse_value(&arg, args.next())?;
+                parsed.explain = Some((query, candidate));
+            }
+            "--inspect" => parsed.inspect = Some(parse_value(&arg, args.next())?),
+            "--neighbors" => parsed.neighbors = Some(parse_value(&arg, args.next())?),
+            "--k" => parsed.k = parse_value(&arg, args.next())?,
+            _ => return Err(format!("unknown argument '{}'", arg)),
+        }
+    }
+    validate(&parsed)?;
+    Ok(parsed)
+}
+
+fn validate(args: &Args) -> Result<(), String> {
+    if args.batch_size == 0 {
+        return Err("batch size must be at least 1".to_string());
+    }
+    if args.semantic_rerank == Some(0) {
+        return Err("semantic rerank must be at least 1".to_string());
+    }
+    // the ellipsis takes one of them
+    if args.truncate_left.is_some_and(|len| len < 2) {
+        return Err("--truncate-left must be at least 2".to_string());
+    }
+    if args.preview_lines == 0 {
+        return Err("preview lines must be at least 1".to_string());
+    }
+    if !(0.0..=1.0).contains(&args.semantic_weight) {
+        return Err("semantic weight must be between 0.0 and 1.0".to_string());
+    }
+    if !(0.0..=1.0).contains(&args.semantic_threshold) {
+        return Err("semantic threshold must be between 0.0 and 1.0".to_string());
+    }
+    // also rejects NaN, which would turn every score into 0
+    if !(args.scoring_config.position_decay >= 0.0
+        && args.scoring_config.position_decay.is_finite())
+    {
+        return Err("position decay must be a non-negative number".to_string());
+    }
+    // a gap of 0 would cut at any lower score, one of 1 never
+    if args.cutoff_gap.is_some_and(|gap| !(gap > 0.0 && gap < 1.0)) {
+        return Err("cutoff gap must be between 0.0 and 1.0, exclusive".to_string());
+    }
+    if let (Some(min), Some(max)) = (args.min_len, args.max_len)
+        && min > max
+    {
+        return Err("minimum length is larger than the maximum length".to_string());
+    }
+    if args.max_edit_distance.is_some() && args.semantic {
+        return Err("--max-edit-distance can't be combined with --semantic".to_string());
+    }
+    if args.json && args.query.is_none() && args.explain.is_none() {
+        return Err("--json requires --query or --explain".to_string());
+    }
+    if args.plain && args.query.is_none() {
+        return Err("--plain requires --query".to_string());
+    }
+    if args.plain && (args.json || args.print0) {
+        return Err("--plain can't be combined with --json or --print0".to_string());
+    }
+    if args.print0 && args.query.is_none() && args.output.is_none() {
+        return Err("--print0 requires --query or --output".to_string());
+    }
+    if args.input_format.columns && (args.input_format.descriptions || args.input_format.keywords) {
+        return Err("--columns can't be combined with --descriptions or --keywords".to_string());
+    }
+    if args.words.is_some() && args.dir.is_some() {
+        return Err("--words can't be combined with --dir".to_string());
+    }
+    if args.print0 && args.json {
+        return Err("--print0 can't be combined with --json".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()), Args::default())
+    }
+
+    #[test]
+    fn pass_flags_disable_their_pass() {
+        let args = parse(&[
+            "--no-substring",
+            "--no-prefix",
+            "--no-subsequence",
+            "--no-edit-distance",
+            "--no-acronym",
+        ])
+        .unwrap();
+        let config = &args.scoring_config;
+        assert!(!config.enable_substring && !config.enable_prefix && !config.enable_subsequence);
+        assert!(!config.enable_edit_distance && !config.enable_acronym);
+        let config = parse(&[]).unwrap().scoring_config;
+        assert!(config.enable_substring && config.enable_prefix && config.enable_subsequence);
+        assert!(config.enable_edit_distance && config.enable_acronym);
+    }
+
+    #[test]
+    fn poll_interval_defaults_to_10_ms() {
+        assert_eq!(parse(&[]).unwrap().poll_ms, 10);
+        assert_eq!(parse(&["--poll-ms", "0"]).unwrap().poll_ms, 0);
+        assert_eq!(parse(&["--poll-ms", "250"]).unwrap().poll_ms, 250);
+        assert!(parse(&["--poll-ms", "-1"]).is_err());
+        assert!(parse(&["--poll-ms"]).is_err());
+    }
+
+    #[test]
+    fn version_flag_is_parsed() {
+        assert!(parse(&["--version"]).unwrap().version);
+        assert!(!parse(&[]).unwrap().version);
+        assert!(parse(&["--versions"]).is_err());
+    }
+
+    #[test]
+    fn minimum_length_above_the_maximum_is_rejected() {
+        let args = parse(&["--min-len", "3", "--max-len", "8"]).unwrap();
+        assert_eq!((args.min_len, args.max_len), (Some(3), Some(8)));
+        assert!(parse(&["--min-len", "9", "--max-len", "8"]).is_err());
+    }
+
+    #[test]
+    fn hint_can_be_replaced_or_disabled() {
+        assert_eq!(parse(&[]).unwrap().hint.as_deref(), Some(DEFAULT_HINT));
+        let args = parse(&["--hint", "nothing here"]).unwrap();
+        assert_eq!(args.hint.as_deref(), Some("nothing here"));
+        assert_eq!(parse(&["--no-hint"]).unwrap().hint, None);
+    }
+
+    #[test]
+    fn limit_is_only_set_by_the_flag() {
+        // without it the terminal height decides
+        assert_eq!(parse(&[]).unwrap().limit, None);
+        assert_eq!(parse(&["--limit", "5"]).unwrap().limit, Some(5));
+        assert!(parse(&["--limit", "many"]).is_err());
+    }
+
+    #[test]
+    fn no_bar_flag_is_parsed() {
+        assert!(!parse(&[]).unwrap().no_bar);
+        assert!(parse(&["--no-bar"]).unwrap().no_bar);
+    }
+
+    #[test]
+    fn semantic_threshold_is_within_0_and_1() {
+        let args = parse(&["--semantic-threshold", "0.35"]).unwrap();
+        assert_eq!(args.semantic_threshold, 0.35);
+        assert!(parse(&["--semantic-threshold", "-0.2"]).is_err());
+        assert!(parse(&["--semantic-threshold", "1.5"]).is_err());
+    }
+
+    #[test]
+    fn explain_takes_a_query_and_a_candidate() {
+        let args = parse(&["--explain", "helo", "hello world"]).unwrap();
+        assert_eq!(
+            args.explain,
+            Some(("helo".to_string(), "hello world".to_string()))
+        );
+        assert!(parse(&["--explain", "helo"]).is_err());
+    }
+
+    #[test]
+    fn plain_excludes_the_other_output_formats() {
+        assert!(parse(&["--query", "x", "--plain"]).unwrap().plain);
+        assert!(parse(&["--query", "x", "--plain", "--json"]).is_err());
+        assert!(parse(&["--query", "x", "--plain", "--print0"]).is_err());
+    }
+
+    #[test]
+    fn pins_add_to_those_of_the_config_file() {
+        let args = parse(&["--pin", "main.rs", "--pin", "lib.rs"]).unwrap();
+        assert_eq!(args.pins, ["main.rs", "lib.rs"]);
+        let config = Args {
+            pins: vec!["Cargo.toml".to_string()],
+            ..Args::default()
+        };
+        let flags = ["--pin", "main.rs"].map(String::from).into_iter();
+        let args = parse_args(flags, config).unwrap();
+        assert_eq!(args.pins, ["Cargo.toml", "main.rs"]);
+    }
+}
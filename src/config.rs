@@ -0,0 +1,208 @@
+use crate::cli::Args;
+use crate::draw::QueryStyle;
+use crate::embedder::{EmbedOrder, ModelSettings};
+use crate::structs::{BarScale, FuzzyError, InputFormat, ScoringConfig, SortOrder};
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// Defaults read from the config file, every value is optional and command line flags win.
+//
+// limit = 30
+// semantic = true
+// semantic-weight = 0.3
+//
+// [scoring]
+// enable-edit-distance = false
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub limit: Option<usize>,
+    pub semantic: Option<bool>,
+    pub semantic_weight: Option<f32>,
+    pub semantic_threshold: Option<f32>,
+    pub semantic_rerank: Option<usize>,
+    pub batch_size: Option<usize>,
+    pub embed_order: Option<EmbedOrder>,
+    pub max_length: Option<usize>,
+    pub threads: Option<usize>,
+    pub browse: Option<bool>,
+    pub icons: Option<bool>,
+    pub descriptions: Option<bool>,
+    pub keywords: Option<bool>,
+    pub raw: Option<bool>,
+    pub columns: Option<bool>,
+    pub match_suffix: Option<bool>,
+    pub show_unmatched: Option<bool>,
+    pub query_style: Option<QueryStyle>,
+    pub poll_ms: Option<u64>,
+    pub export_path: Option<String>,
+    pub min_len: Option<usize>,
+    pub max_len: Option<usize>,
+    pub max_scan: Option<usize>,
+    pub query_timeout_ms: Option<u64>,
+    // an empty hint disables it
+    pub hint: Option<String>,
+    pub max_edit_distance: Option<usize>,
+    pub timing_log: Option<String>,
+    pub no_bar: Option<bool>,
+    pub bar_scale: Option<BarScale>,
+    pub mark_semantic: Option<bool>,
+    pub frecency: Option<bool>,
+    pub numbered: Option<bool>,
+    pub truncate_left: Option<usize>,
+    pub words: Option<String>,
+    pub preview: Option<String>,
+    pub preview_lines: Option<usize>,
+    pub sort: Option<SortOrder>,
+    pub reverse: Option<bool>,
+    pub tiers: Option<bool>,
+    pub pins: Option<Vec<String>>,
+    pub quiet: Option<bool>,
+    pub cutoff_gap: Option<f32>,
+    pub scoring: Option<ScoringConfig>,
+}
+
+impl Config {
+    pub fn into_args(self) -> Args {
+        let defaults = Args::default();
+        Args {
+            limit: self.limit.or(defaults.limit),
+            semantic: self.semantic.unwrap_or(defaults.semantic),
+            semantic_weight: self.semantic_weight.unwrap_or(defaults.semantic_weight),
+            semantic_threshold: self
+                .semantic_threshold
+                .unwrap_or(defaults.semantic_threshold),
+            semantic_rerank: self.semantic_rerank.or(defaults.semantic_rerank),
+            batch_size: self.batch_size.unwrap_or(defaults.batch_size),
+            embed_order: self.embed_order.unwrap_or(defaults.embed_order),
+            browse: self.browse.unwrap_or(defaults.browse),
+            input_format: InputFormat {
+                icons: self.icons.unwrap_or(defaults.input_format.icons),
+                descriptions: self
+                    .descriptions
+                    .unwrap_or(defaults.input_format.descriptions),
+                keywords: self.keywords.unwrap_or(defaults.input_format.keywords),
+                raw: self.raw.unwrap_or(defaults.input_format.raw),
+                columns: self.columns.unwrap_or(defaults.input_format.columns),
+            },
+            match_suffix: self.match_suffix.unwrap_or(defaults.match_suffix),
+            show_unmatched: self.show_unmatched.unwrap_or(defaults.show_unmatched),
+            query_style: self.query_style.unwrap_or(defaults.query_style),
+            poll_ms: self.poll_ms.unwrap_or(defaults.poll_ms),
+            export_path: self.export_path.unwrap_or(defaults.export_path),
+            min_len: self.min_len,
+            max_len: self.max_len,
+            max_scan: self.max_scan,
+            query_timeout_ms: self.query_timeout_ms,
+            hint: self.hint.or(defaults.hint),
+            max_edit_distance: self.max_edit_distance,
+            timing_log: self.timing_log,
+            no_bar: self.no_bar.unwrap_or(defaults.no_bar),
+            bar_scale: self.bar_scale.unwrap_or(defaults.bar_scale),
+            mark_semantic: self.mark_semantic.unwrap_or(defaults.mark_semantic),
+            frecency: self.frecency.unwrap_or(defaults.frecency),
+            numbered: self.numbered.unwrap_or(defaults.numbered),
+            truncate_left: self.truncate_left.or(defaults.truncate_left),
+            words: self.words,
+            preview: self.preview,
+            preview_lines: self.preview_lines.unwrap_or(defaults.preview_lines),
+            sort: self.sort.unwrap_or(defaults.sort),
+            reverse: self.reverse.unwrap_or(defaults.reverse),
+            tiers: self.tiers.unwrap_or(defaults.tiers),
+            pins: self.pins.unwrap_or(defaults.pins),
+            quiet: self.quiet.unwrap_or(defaults.quiet),
+            cutoff_gap: self.cutoff_gap.or(defaults.cutoff_gap),
+            model_settings: ModelSettings {
+                max_length: self.max_length,
+                threads: self.threads,
+            },
+            scoring_config: self.scoring.unwrap_or_default(),
+            ..defaults
+        }
+    }
+}
+
+// $XDG_CONFIG_HOME/fuzzyq/config.toml, falling back to ~/.config/fuzzyq/config.toml
+pub fn config_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("fuzzyq").join("config.toml"))
+}
+
+// $XDG_DATA_HOME/fuzzyq/frecency.tsv, falling back to ~/.local/share/fuzzyq/frecency.tsv
+pub fn frecency_path() -> Option<PathBuf> {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+        })?;
+    Some(data_dir.join("fuzzyq").join("frecency.tsv"))
+}
+
+// a missing file means built-in defaults, a malformed one is an error
+pub fn load_config() -> Result<Config, FuzzyError> {
+    let Some(path) = config_path() else {
+        return Ok(Config::default());
+    };
+    let path_str = path.display().to_string();
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(err) => return Err(FuzzyError::io(&path_str, err)),
+    };
+    toml::from_str(&contents).map_err(|err| FuzzyError::Config {
+        path: path_str,
+        message: err.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+
+    #[test]
+    fn sample_config_sets_the_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            limit = 30
+            semantic = true
+            semantic-weight = 0.3
+            export-path = "results.txt"
+            pins = ["main.rs"]
+
+            [scoring]
+            enable-edit-distance = false
+            "#,
+        )
+        .unwrap();
+        let args = config.into_args();
+        assert_eq!(args.limit, Some(30));
+        assert!(args.semantic);
+        assert_eq!(args.semantic_weight, 0.3);
+        assert_eq!(args.export_path, "results.txt");
+        assert_eq!(args.pins, ["main.rs"]);
+        assert!(!args.scoring_config.enable_edit_distance);
+        // what the file leaves out keeps the built-in default
+        assert!(args.scoring_config.enable_prefix);
+        assert_eq!(args.poll_ms, 10);
+
+        // flags still win over the file
+        let flags = ["--limit", "5"].map(String::from).into_iter();
+        let args = cli::parse_args(flags, args).unwrap();
+        assert_eq!(args.limit, Some(5));
+        assert!(args.semantic);
+    }
+
+    #[test]
+    fn malformed_configs_are_rejected() {
+        assert!(toml::from_str::<Config>("limit = \"many\"").is_err());
+        assert!(toml::from_str::<Config>("unknown-option = true").is_err());
+        assert!(toml::from_str::<Config>("[scoring]\nenable-everything = true").is_err());
+        let empty: Config = toml::from_str("").unwrap();
+        assert_eq!(empty.into_args().limit, None);
+    }
+}
@@ -1,17 +1,71 @@
 use crossterm::{
     cursor, execute,
-    style::{Color, Print, SetForegroundColor},
+    style::{Attribute, Color, Print, SetAttribute, SetBackgroundColor, SetForegroundColor},
     terminal::{self, Clear, ClearType},
 };
 
-use std::io;
+use serde::Deserialize;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::str::FromStr;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-use crate::structs::Suggestion;
+use crate::structs::{BarScale, DrawConfig, QueryInput, Suggestion};
+
+// how the typed query is drawn after the "Search query: " prompt, unmatched characters stay red
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum QueryStyle {
+    Plain,
+    #[default]
+    Bold,
+    // a crossterm color name, e.g. cyan or dark_green
+    Color(Color),
+}
+
+impl FromStr for QueryStyle {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "plain" => Ok(QueryStyle::Plain),
+            "bold" => Ok(QueryStyle::Bold),
+            _ => Color::try_from(value).map(QueryStyle::Color),
+        }
+    }
+}
+
+impl TryFrom<String> for QueryStyle {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .parse()
+            .map_err(|_| format!("invalid query style '{}'", value))
+    }
+}
+
+// Rows below the header taken up by the last drawn suggestions. Only the draw functions update it,
+// so the next clear covers exactly what was drawn however the number of suggestions changes.
+#[derive(Default)]
+pub struct DrawnRows {
+    count: usize,
+}
+
+// how many suggestions fit below the header and the reserved rows (e.g. the preview), at least one
+// so tiny terminals still show something
+pub fn fitting_suggestions(terminal_rows: u16, reserved_rows: usize) -> usize {
+    (terminal_rows as usize)
+        .saturating_sub(1 + reserved_rows)
+        .max(1)
+}
 
 pub fn clear_previous_suggestions(
-    stdout: &mut io::Stdout,
-    last_suggestion_count: usize,
+    stdout: &mut impl Write,
+    drawn: &mut DrawnRows,
 ) -> io::Result<()> {
+    let last_suggestion_count = std::mem::take(&mut drawn.count);
     for _ in 0..last_suggestion_count {
         execute!(
             stdout,
@@ -26,55 +80,278 @@ pub fn clear_previous_suggestions(
     Ok(())
 }
 
-pub fn draw_suggestions(stdout: &mut io::Stdout, suggestions: &[Suggestion]) -> io::Result<()> {
+const SEMANTIC_MARKER: &str = " ~";
+
+fn shows_semantic_marker(sug: &Suggestion, config: &DrawConfig) -> bool {
+    config.mark_semantic && sug.semantic_only
+}
+
+// drawn in place of the start of a label that is cut with truncate_left
+const ELLIPSIS: &str = "…";
+
+// Byte offset of the first drawn char of the label, 0 when the whole label is drawn. It's cut
+// between grapheme clusters, so no accent loses its letter. Lines with their own ANSI escapes are
+// never cut, that could split an escape.
+fn hidden_prefix(sug: &Suggestion, config: &DrawConfig) -> usize {
+    let Some(max_chars) = config.truncate_left else {
+        return 0;
+    };
+    if sug.display.is_some() || sug.text.graphemes(true).count() <= max_chars {
+        return 0;
+    }
+    // the last max_chars - 1 clusters, after the ellipsis
+    sug.text
+        .grapheme_indices(true)
+        .rev()
+        .nth(max_chars.saturating_sub(1))
+        .map_or(0, |(i, grapheme)| i + grapheme.len())
+}
+
+// width of the text column: the icon, the text, the semantic marker and the dimmed description
+fn display_width(sug: &Suggestion, config: &DrawConfig) -> usize {
+    let hidden = hidden_prefix(sug, config);
+    // terminal columns rather than bytes, e.g. Hebrew takes two bytes but one column per letter
+    // and its vowel points none
+    let label_width = if hidden > 0 {
+        ELLIPSIS.width() + sug.text[hidden..].width()
+    } else {
+        sug.text.width()
+    };
+    // icons are often emoji that take two columns
+    sug.icon.as_ref().map_or(0, |icon| icon.width() + 1)
+        + label_width
+        + if shows_semantic_marker(sug, config) {
+            SEMANTIC_MARKER.len()
+        } else {
+            0
+        }
+        + sug.description.as_ref().map_or(0, |desc| desc.width() + 1)
+}
+
+// The text from the byte offset hidden on, with the matched characters in green. The text is only
+// sliced between grapheme clusters, and a cluster is highlighted as a whole when a match index
+// falls anywhere in it, so combining marks (e.g. Hebrew vowel points) stay with their letter and
+// right-to-left runs reach the terminal in one piece. Match indices in the hidden prefix or past
+// the end (e.g. computed on another text) are ignored.
+fn draw_highlighted(
+    stdout: &mut impl Write,
+    sug: &Suggestion,
+    text_color: Color,
+    hidden: usize,
+) -> io::Result<()> {
+    if hidden > 0 {
+        execute!(stdout, SetForegroundColor(text_color), Print(ELLIPSIS))?;
+    }
+    let mut matched: Vec<usize> = sug
+        .match_indices
+        .iter()
+        .copied()
+        .filter(|&idx| idx >= hidden && idx < sug.text.len())
+        .collect();
+    matched.sort_unstable();
+    let mut pending = matched.into_iter().peekable();
+
+    let clusters = sug.text[hidden..]
+        .grapheme_indices(true)
+        .map(|(offset, grapheme)| {
+            let start = hidden + offset;
+            let end = start + grapheme.len();
+            let mut is_match = false;
+            while pending.next_if(|&idx| idx < end).is_some() {
+                is_match = true;
+            }
+            (start..end, is_match)
+        });
+    for (range, is_match) in highlight_ranges(clusters) {
+        let color = if is_match { Color::Green } else { text_color };
+        execute!(stdout, SetForegroundColor(color), Print(&sug.text[range]))?;
+    }
+    Ok(())
+}
+
+// Merges consecutive pieces of a text (byte ranges) that are both highlighted or both not into
+// one, so every run is a single color switch and Print rather than one per character.
+fn highlight_ranges(
+    pieces: impl Iterator<Item = (Range<usize>, bool)>,
+) -> Vec<(Range<usize>, bool)> {
+    let mut runs: Vec<(Range<usize>, bool)> = Vec::new();
+    for (range, highlighted) in pieces {
+        match runs.last_mut() {
+            Some((run, run_highlighted)) if *run_highlighted == highlighted => run.end = range.end,
+            _ => runs.push((range, highlighted)),
+        }
+    }
+    runs
+}
+
+// Widths of the metadata columns, without the trailing ones that don't fit in available. Every
+// column takes two more for the spaces before it.
+fn column_widths(suggestions: &[Suggestion], available: usize) -> Vec<usize> {
+    let count = suggestions
+        .iter()
+        .map(|sug| sug.columns.len())
+        .max()
+        .unwrap_or(0);
+    let mut widths = Vec::with_capacity(count);
+    let mut used = 0;
+    for column in 0..count {
+        let width = suggestions
+            .iter()
+            .filter_map(|sug| sug.columns.get(column))
+            .map(|value| value.width())
+            .max()
+            .unwrap_or(0);
+        if used + 2 + width > available {
+            break;
+        }
+        used += 2 + width;
+        widths.push(width);
+    }
+    widths
+}
+
+pub fn draw_suggestions(
+    stdout: &mut impl Write,
+    suggestions: &[Suggestion],
+    selected: Option<usize>,
+    config: &DrawConfig,
+    drawn: &mut DrawnRows,
+) -> io::Result<()> {
+    // counted up front so a draw that fails halfway is still cleared completely
+    drawn.count = suggestions.len();
     let longest_suggestion = suggestions
         .iter()
-        .map(|sug| sug.text.len())
+        .map(|sug| display_width(sug, config))
         .max()
         .unwrap_or(0);
-    let lowest_score = suggestions.iter().map(|sug| sug.score).min().unwrap_or(0);
+    let lowest_score = suggestions
+        .iter()
+        .map(Suggestion::normalized_score)
+        .fold(1.0, f32::min);
+    let highest_score = suggestions
+        .iter()
+        .map(Suggestion::normalized_score)
+        .fold(0.0, f32::max);
+    // `N. ` with the ranks padded to the widest one, so the suggestions stay aligned
+    let rank_width = suggestions.len().to_string().len();
+    let prefix_width = if config.numbered { rank_width + 2 } else { 0 };
     let terminal_width = terminal::size().unwrap_or((80, 24)).0 as usize;
-    let bar_width = terminal_width - longest_suggestion - 10;
-    for sug in suggestions {
+    // the bar keeps some room, the columns get what's left next to the suggestions
+    let bar_room = if config.show_bar { 10 } else { 0 };
+    let widths = column_widths(
+        suggestions,
+        terminal_width.saturating_sub(prefix_width + longest_suggestion + bar_room),
+    );
+    let table_width =
+        prefix_width + longest_suggestion + widths.iter().map(|width| width + 2).sum::<usize>();
+    let bar_width = terminal_width.saturating_sub(table_width + 10);
+    for (i, sug) in suggestions.iter().enumerate() {
+        let is_selected = selected == Some(i);
+        // the selected row gets a background over the full width and a contrasting foreground
+        let (text_color, dim_color) = if is_selected {
+            (Color::White, Color::Grey)
+        } else {
+            (Color::Reset, Color::DarkGrey)
+        };
         execute!(
             stdout,
             cursor::MoveDown(1),
             cursor::MoveToColumn(0),
             Clear(ClearType::CurrentLine)
         )?;
+        if is_selected {
+            execute!(stdout, SetBackgroundColor(Color::DarkBlue))?;
+        }
+
+        if config.numbered {
+            execute!(
+                stdout,
+                SetForegroundColor(dim_color),
+                Print(format!("{:>width$}. ", i + 1, width = rank_width))
+            )?;
+        }
+
+        // printed before the text, so match indices stay relative to the text
+        if let Some(icon) = &sug.icon {
+            execute!(
+                stdout,
+                SetForegroundColor(text_color),
+                Print(icon),
+                Print(" ")
+            )?;
+        }
 
-        let mut last_idx = 0;
-        for &idx in &sug.match_indices {
-            if idx > last_idx {
+        match &sug.display {
+            // the input's own colors are kept instead of highlighting the matched characters
+            Some(display) => {
+                execute!(stdout, Print(display), SetAttribute(Attribute::Reset))?;
+                if is_selected {
+                    execute!(stdout, SetBackgroundColor(Color::DarkBlue))?;
+                }
+            }
+            None => draw_highlighted(stdout, sug, text_color, hidden_prefix(sug, config))?,
+        }
+        if shows_semantic_marker(sug, config) {
+            execute!(
+                stdout,
+                SetForegroundColor(Color::Magenta),
+                Print(SEMANTIC_MARKER)
+            )?;
+        }
+        if let Some(description) = &sug.description {
+            execute!(
+                stdout,
+                SetForegroundColor(dim_color),
+                Print(" "),
+                Print(description)
+            )?;
+        }
+        let mut line_width = prefix_width + display_width(sug, config);
+        if !widths.is_empty() {
+            // right aligned, after the suggestions are padded to the same width
+            execute!(
+                stdout,
+                Print(" ".repeat(prefix_width + longest_suggestion - line_width)),
+                SetForegroundColor(text_color)
+            )?;
+            for (column, &width) in widths.iter().enumerate() {
+                let value = sug.columns.get(column).map_or("", String::as_str);
                 execute!(
                     stdout,
-                    SetForegroundColor(Color::Reset),
-                    Print(&sug.text[last_idx..idx])
+                    Print(" ".repeat(2 + width - value.width())),
+                    Print(value)
                 )?;
             }
+            line_width = table_width;
+        }
+        if config.show_bar {
+            let score_ratio = match config.bar_scale {
+                BarScale::Spread => sug.normalized_score() - lowest_score,
+                BarScale::Relative if highest_score > 0.0 => sug.normalized_score() / highest_score,
+                BarScale::Relative => 0.0,
+                BarScale::Absolute => sug.normalized_score(),
+            };
+            let score_value_string = format!(" {}", sug.score as f32);
+            let score_bar_string = "█".repeat((score_ratio * bar_width as f32).round() as usize);
+            // pad with spaces rather than jumping columns so the background has no holes
+            let bar_padding = " ".repeat(table_width + 2 - line_width);
+            line_width =
+                table_width + 2 + score_bar_string.chars().count() + score_value_string.len();
             execute!(
                 stdout,
-                SetForegroundColor(Color::Green),
-                Print(&sug.text[idx..idx + 1])
+                Print(bar_padding),
+                SetForegroundColor(dim_color),
+                Print(score_bar_string + &score_value_string),
             )?;
-            last_idx = idx + 1;
         }
-        if last_idx < sug.text.len() {
+        if is_selected {
             execute!(
                 stdout,
-                SetForegroundColor(Color::Reset),
-                Print(&sug.text[last_idx..])
+                Print(" ".repeat(terminal_width.saturating_sub(line_width))),
+                SetBackgroundColor(Color::Reset),
             )?;
         }
-        let score_ratio = (sug.score as f32 - lowest_score as f32) / 1000 as f32;
-        let score_value_string = format!(" {}", sug.score as f32);
-        let score_bar_string = "█".repeat((score_ratio * bar_width as f32).round() as usize);
-        execute!(
-            stdout,
-            cursor::MoveToColumn(longest_suggestion as u16 + 2),
-            SetForegroundColor(Color::DarkGrey),
-            Print(score_bar_string + &score_value_string),
-        )?;
+        execute!(stdout, SetForegroundColor(Color::Reset))?;
     }
 
     if !suggestions.is_empty() {
@@ -83,8 +360,68 @@ pub fn draw_suggestions(stdout: &mut io::Stdout, suggestions: &[Suggestion]) ->
     Ok(())
 }
 
-pub fn draw_header(stdout: &mut io::Stdout, typed: &str, delta_time: f64) -> io::Result<()> {
-    let delta_time_str = format!("{:.2}ms", delta_time * 1000.0);
+// A separator and up to height lines of the preview below what is drawn already. Lines are cut
+// at the terminal width so the pane keeps its height.
+pub fn draw_preview(
+    stdout: &mut impl Write,
+    lines: &[String],
+    height: usize,
+    drawn: &mut DrawnRows,
+) -> io::Result<()> {
+    let width = terminal::size().unwrap_or((80, 24)).0 as usize;
+    let above = drawn.count;
+    execute!(stdout, cursor::MoveDown(above as u16 + 1))?;
+    execute!(
+        stdout,
+        cursor::MoveToColumn(0),
+        Clear(ClearType::CurrentLine),
+        SetForegroundColor(Color::DarkGrey),
+        Print("─".repeat(width)),
+        SetForegroundColor(Color::Reset)
+    )?;
+    let shown = lines.len().min(height);
+    for line in &lines[..shown] {
+        let line: String = line.chars().take(width).collect();
+        execute!(
+            stdout,
+            cursor::MoveDown(1),
+            cursor::MoveToColumn(0),
+            Clear(ClearType::CurrentLine),
+            Print(line)
+        )?;
+    }
+    drawn.count += 1 + shown;
+    execute!(stdout, cursor::MoveUp((above + 1 + shown) as u16))
+}
+
+// a dimmed line in place of the suggestions, e.g. when nothing matches
+pub fn draw_hint(stdout: &mut impl Write, hint: &str, drawn: &mut DrawnRows) -> io::Result<()> {
+    drawn.count = 1;
+    execute!(
+        stdout,
+        cursor::MoveDown(1),
+        cursor::MoveToColumn(0),
+        Clear(ClearType::CurrentLine),
+        SetForegroundColor(Color::DarkGrey),
+        Print(hint),
+        SetForegroundColor(Color::Reset),
+        cursor::MoveUp(1)
+    )
+}
+
+// query characters at unmatched_indices (char positions) are drawn in red,
+// an optional notice is shown in yellow next to the search mode and timing
+pub fn draw_header(
+    stdout: &mut impl Write,
+    query: &QueryInput,
+    unmatched_indices: &[usize],
+    notice: Option<&str>,
+    mode: &str,
+    delta_time: f64,
+    query_style: QueryStyle,
+) -> io::Result<()> {
+    let delta_time_str = format!("{}  {:.2}ms", mode, delta_time * 1000.0);
+    let notice_str = notice.map_or(String::new(), |notice| format!("{}  ", notice));
     let (width, _) = terminal::size().unwrap_or((80, 24));
     let query_hint = "Search query: ";
     execute!(
@@ -93,12 +430,667 @@ pub fn draw_header(stdout: &mut io::Stdout, typed: &str, delta_time: f64) -> io:
         Clear(ClearType::CurrentLine),
         SetForegroundColor(Color::Reset),
         Print(query_hint),
-        Print(&typed),
-        cursor::MoveToColumn(width.saturating_sub(delta_time_str.len() as u16)),
+    )?;
+    let matched_color = match query_style {
+        QueryStyle::Color(color) => color,
+        QueryStyle::Plain | QueryStyle::Bold => Color::Reset,
+    };
+    if let QueryStyle::Bold = query_style {
+        execute!(stdout, SetAttribute(Attribute::Bold))?;
+    }
+    let text = query.text();
+    let chars = text
+        .char_indices()
+        .enumerate()
+        .map(|(i, (start, ch))| (start..start + ch.len_utf8(), unmatched_indices.contains(&i)));
+    for (range, unmatched) in highlight_ranges(chars) {
+        let color = if unmatched { Color::Red } else { matched_color };
+        execute!(stdout, SetForegroundColor(color), Print(&text[range]))?;
+    }
+    execute!(
+        stdout,
+        // the style only covers the query, not the notice and timing after it
+        SetAttribute(Attribute::Reset),
+        cursor::MoveToColumn(
+            width.saturating_sub((notice_str.chars().count() + delta_time_str.len()) as u16)
+        ),
+        SetForegroundColor(Color::Yellow),
+        Print(&notice_str),
         SetForegroundColor(Color::DarkGrey),
         Print(&delta_time_str),
         SetForegroundColor(Color::Reset),
-        cursor::MoveToColumn((typed.len() + query_hint.len()) as u16)
+        cursor::MoveToColumn((query_hint.len() + query.cursor_column()) as u16)
     )?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::SearchMode;
+
+    fn escapes(command: impl crossterm::Command) -> String {
+        let mut buffer = Vec::new();
+        execute!(buffer, command).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    fn suggestion(text: &str) -> Suggestion {
+        Suggestion {
+            text: text.to_string(),
+            score: 500,
+            ..Default::default()
+        }
+    }
+
+    fn draw_config() -> DrawConfig {
+        DrawConfig {
+            show_bar: false,
+            mark_semantic: false,
+            numbered: false,
+            bar_scale: BarScale::Spread,
+            truncate_left: None,
+        }
+    }
+
+    #[test]
+    fn selected_row_has_a_full_width_background() {
+        let suggestions = [suggestion("apple"), suggestion("banana")];
+        let mut buffer = Vec::new();
+        let config = draw_config();
+        draw_suggestions(
+            &mut buffer,
+            &suggestions,
+            Some(1),
+            &config,
+            &mut DrawnRows::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let rows: Vec<&str> = output.split(&escapes(cursor::MoveDown(1))).collect();
+        let background = escapes(SetBackgroundColor(Color::DarkBlue));
+        assert!(rows[1].contains("apple") && !rows[1].contains(&background));
+        assert!(rows[2].contains("banana") && rows[2].contains(&background));
+        assert!(rows[2].contains(&escapes(SetForegroundColor(Color::White))));
+        // padded to the terminal width, then the background is reset
+        let width = terminal::size().map_or(80, |(width, _)| width as usize);
+        let padding = " ".repeat(width - "banana".len());
+        let end = format!(
+            "{}{}{}",
+            padding,
+            escapes(SetBackgroundColor(Color::Reset)),
+            escapes(SetForegroundColor(Color::Reset))
+        );
+        assert!(rows[2].contains(&end));
+    }
+
+    #[test]
+    fn unmatched_query_characters_are_red() {
+        let mut query = QueryInput::default();
+        query.insert_str("hexlo");
+        let mut buffer = Vec::new();
+        draw_header(
+            &mut buffer,
+            &query,
+            &[2],
+            None,
+            "lexical",
+            0.0,
+            QueryStyle::Plain,
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let red = escapes(SetForegroundColor(Color::Red));
+        let plain = escapes(SetForegroundColor(Color::Reset));
+        assert!(output.contains(&format!("{plain}he{red}x{plain}lo")));
+    }
+
+    #[test]
+    fn descriptions_are_dimmed_after_the_label() {
+        let sug = Suggestion {
+            description: Some("Web browser".to_string()),
+            ..suggestion("Firefox")
+        };
+        let mut buffer = Vec::new();
+        draw_suggestions(
+            &mut buffer,
+            &[sug],
+            None,
+            &draw_config(),
+            &mut DrawnRows::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let dimmed = format!(
+            "Firefox{} Web browser",
+            escapes(SetForegroundColor(Color::DarkGrey))
+        );
+        assert!(output.contains(&dimmed));
+    }
+
+    #[test]
+    fn icons_are_drawn_before_the_highlights_without_shifting_them() {
+        let config = crate::structs::ScoringConfig::default();
+        let sug = Suggestion {
+            icon: Some("📁".to_string()),
+            ..crate::algorithms::fuzzy_match("Doc", "Documents", &config).unwrap()
+        };
+        assert_eq!(sug.match_indices, [0, 1, 2]);
+        // the emoji takes two columns, then a space
+        assert_eq!(display_width(&sug, &draw_config()), 3 + "Documents".len());
+        let mut buffer = Vec::new();
+        draw_suggestions(
+            &mut buffer,
+            &[sug],
+            None,
+            &draw_config(),
+            &mut DrawnRows::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let green = escapes(SetForegroundColor(Color::Green));
+        assert!(output.contains(&format!("📁 {green}Doc")));
+    }
+
+    #[test]
+    fn clearing_matches_the_rows_drawn_last() {
+        let all: Vec<Suggestion> = (0..5).map(|i| suggestion(&format!("option {i}"))).collect();
+        let mut drawn = DrawnRows::default();
+        let clear_line = escapes(Clear(ClearType::CurrentLine));
+        // what clearing after drawing that many rows emits
+        let mut redraw = |count: usize| {
+            let mut buffer = Vec::new();
+            draw_suggestions(&mut buffer, &all[..count], None, &draw_config(), &mut drawn).unwrap();
+            let mut buffer = Vec::new();
+            clear_previous_suggestions(&mut buffer, &mut drawn).unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        for count in [5, 2, 4] {
+            let cleared = redraw(count);
+            assert_eq!(cleared.matches(&clear_line).count(), count);
+            assert!(cleared.ends_with(&escapes(cursor::MoveUp(count as u16))));
+        }
+        // cleared rows aren't cleared again
+        let mut buffer = Vec::new();
+        clear_previous_suggestions(&mut buffer, &mut drawn).unwrap();
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn empty_results_hint_is_a_dimmed_row_cleared_like_a_suggestion() {
+        let mut drawn = DrawnRows::default();
+        let mut buffer = Vec::new();
+        draw_suggestions(&mut buffer, &[], None, &draw_config(), &mut drawn).unwrap();
+        draw_hint(&mut buffer, crate::cli::DEFAULT_HINT, &mut drawn).unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let dimmed = escapes(SetForegroundColor(Color::DarkGrey));
+        assert!(output.contains(&format!("{dimmed}{}", crate::cli::DEFAULT_HINT)));
+        // the cursor goes back to the query
+        assert!(output.ends_with(&escapes(cursor::MoveUp(1))));
+        let mut buffer = Vec::new();
+        clear_previous_suggestions(&mut buffer, &mut drawn).unwrap();
+        let cleared = String::from_utf8(buffer).unwrap();
+        assert_eq!(
+            cleared
+                .matches(&escapes(Clear(ClearType::CurrentLine)))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn suggestions_fill_the_terminal_below_the_header() {
+        assert_eq!(fitting_suggestions(24, 0), 23);
+        assert_eq!(fitting_suggestions(24, 10), 13);
+        // tiny terminals and large previews still show one
+        assert_eq!(fitting_suggestions(2, 0), 1);
+        assert_eq!(fitting_suggestions(1, 0), 1);
+        assert_eq!(fitting_suggestions(0, 0), 1);
+        assert_eq!(fitting_suggestions(12, 40), 1);
+    }
+
+    #[test]
+    fn no_bar_draws_neither_the_bar_nor_the_score() {
+        let suggestions = [
+            Suggestion {
+                score: 1000,
+                ..suggestion("apple")
+            },
+            suggestion("apricot"),
+        ];
+        let draw = |show_bar: bool| {
+            let mut buffer = Vec::new();
+            let config = DrawConfig {
+                show_bar,
+                ..draw_config()
+            };
+            draw_suggestions(
+                &mut buffer,
+                &suggestions,
+                None,
+                &config,
+                &mut DrawnRows::default(),
+            )
+            .unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        let with_bar = draw(true);
+        assert!(with_bar.contains('█') && with_bar.contains(" 1000") && with_bar.contains(" 500"));
+        let without = draw(false);
+        assert!(!without.contains('█') && !without.contains(" 1000") && !without.contains(" 500"));
+        assert!(without.contains("apricot"));
+    }
+
+    #[test]
+    fn semantic_only_suggestions_are_marked() {
+        let lexical = Suggestion {
+            match_indices: vec![0, 1],
+            ..suggestion("car")
+        };
+        let semantic = Suggestion {
+            semantic_only: true,
+            ..suggestion("automobile")
+        };
+        let draw = |sug: &Suggestion, mark_semantic: bool| {
+            let mut buffer = Vec::new();
+            let config = DrawConfig {
+                mark_semantic,
+                ..draw_config()
+            };
+            draw_suggestions(
+                &mut buffer,
+                std::slice::from_ref(sug),
+                None,
+                &config,
+                &mut DrawnRows::default(),
+            )
+            .unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        let marker = format!(
+            "automobile{}{SEMANTIC_MARKER}",
+            escapes(SetForegroundColor(Color::Magenta))
+        );
+        assert!(draw(&semantic, true).contains(&marker));
+        assert!(!draw(&semantic, false).contains(SEMANTIC_MARKER));
+        // a lexical match is highlighted instead
+        let drawn = draw(&lexical, true);
+        assert!(!drawn.contains(SEMANTIC_MARKER));
+        assert!(drawn.contains(&format!("{}ca", escapes(SetForegroundColor(Color::Green)))));
+        assert_eq!(
+            display_width(
+                &semantic,
+                &DrawConfig {
+                    mark_semantic: true,
+                    ..draw_config()
+                }
+            ),
+            12
+        );
+    }
+
+    #[test]
+    fn header_shows_the_search_mode() {
+        let mut buffer = Vec::new();
+        let query = QueryInput::default();
+        draw_header(
+            &mut buffer,
+            &query,
+            &[],
+            None,
+            SearchMode::Semantic.label(),
+            0.0042,
+            QueryStyle::Plain,
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        assert!(output.contains(&format!(
+            "{}semantic  4.20ms",
+            escapes(SetForegroundColor(Color::DarkGrey))
+        )));
+    }
+
+    #[test]
+    fn header_leaves_the_cursor_in_the_query() {
+        let mut query = QueryInput::default();
+        query.insert_str("añ漢b");
+        query.move_left();
+        let mut buffer = Vec::new();
+        draw_header(
+            &mut buffer,
+            &query,
+            &[],
+            None,
+            "lexical",
+            0.0,
+            QueryStyle::Plain,
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        // after "Search query: " and the columns of the chars before the cursor, the last of them
+        // two wide, not their six bytes or three chars
+        let column = escapes(cursor::MoveToColumn(("Search query: ".len() + 4) as u16));
+        assert!(output.ends_with(&column));
+    }
+
+    #[test]
+    fn raw_lines_are_drawn_with_their_own_colors() {
+        let colored = "\x1b[31mred\x1b[0m line";
+        let sug = Suggestion {
+            display: Some(colored.to_string()),
+            match_indices: vec![0, 1, 2],
+            ..suggestion("red line")
+        };
+        let mut buffer = Vec::new();
+        draw_suggestions(
+            &mut buffer,
+            &[sug],
+            None,
+            &draw_config(),
+            &mut DrawnRows::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        assert!(output.contains(colored));
+        assert!(!output.contains(&escapes(SetForegroundColor(Color::Green))));
+    }
+
+    #[test]
+    fn numbered_ranks_are_right_aligned() {
+        let suggestions: Vec<Suggestion> = (1..=10)
+            .map(|i| suggestion(&format!("option {i}")))
+            .collect();
+        let config = DrawConfig {
+            numbered: true,
+            ..draw_config()
+        };
+        let mut buffer = Vec::new();
+        draw_suggestions(
+            &mut buffer,
+            &suggestions,
+            None,
+            &config,
+            &mut DrawnRows::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let rows: Vec<&str> = output.split(&escapes(cursor::MoveDown(1))).collect();
+        let rank = |n: &str| format!("{}{n}. ", escapes(SetForegroundColor(Color::DarkGrey)));
+        assert!(rows[1].contains(&rank(" 1")));
+        assert!(rows[10].contains(&rank("10")));
+        // the selected row is padded to the terminal width, counting the rank
+        let mut buffer = Vec::new();
+        draw_suggestions(
+            &mut buffer,
+            &suggestions[..1],
+            Some(0),
+            &config,
+            &mut DrawnRows::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let width = terminal::size().map_or(80, |(width, _)| width as usize);
+        let padding = " ".repeat(width - "1. option 1".len());
+        assert!(output.contains(&format!(
+            "option 1{padding}{}",
+            escapes(SetBackgroundColor(Color::Reset))
+        )));
+    }
+
+    #[test]
+    fn preview_is_cut_to_its_height_and_the_terminal_width() {
+        let width = terminal::size().map_or(80, |(width, _)| width as usize);
+        let lines = [
+            "x".repeat(width + 5),
+            "second".to_string(),
+            "third".to_string(),
+        ];
+        let mut drawn = DrawnRows::default();
+        let mut buffer = Vec::new();
+        draw_suggestions(
+            &mut buffer,
+            &[suggestion("a"), suggestion("b")],
+            None,
+            &draw_config(),
+            &mut drawn,
+        )
+        .unwrap();
+        let mut buffer = Vec::new();
+        draw_preview(&mut buffer, &lines, 2, &mut drawn).unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        assert!(output.contains(&"x".repeat(width)) && !output.contains(&"x".repeat(width + 1)));
+        assert!(output.contains("second") && !output.contains("third"));
+        // the separator and two lines below the two suggestions, all cleared next time
+        assert_eq!(drawn.count, 5);
+        assert!(output.ends_with(&escapes(cursor::MoveUp(5))));
+    }
+
+    #[test]
+    fn bar_widths_follow_the_bar_scale() {
+        let suggestions: Vec<Suggestion> = [800, 400, 200]
+            .iter()
+            .map(|&score| Suggestion {
+                score,
+                ..suggestion("abc")
+            })
+            .collect();
+        let bars = |bar_scale| -> Vec<usize> {
+            let config = DrawConfig {
+                show_bar: true,
+                bar_scale,
+                ..draw_config()
+            };
+            let mut buffer = Vec::new();
+            draw_suggestions(
+                &mut buffer,
+                &suggestions,
+                None,
+                &config,
+                &mut DrawnRows::default(),
+            )
+            .unwrap();
+            let output = String::from_utf8(buffer).unwrap();
+            output
+                .split(&escapes(cursor::MoveDown(1)))
+                .skip(1)
+                .map(|row| row.matches('█').count())
+                .collect()
+        };
+        let bar_width = terminal::size().map_or(80, |(width, _)| width as usize) - "abc".len() - 10;
+        let share = |fraction: f32| (fraction * bar_width as f32).round() as usize;
+        // the best of this query gets the full width
+        assert_eq!(
+            bars(BarScale::Relative),
+            [bar_width, share(0.5), share(0.25)]
+        );
+        assert_eq!(
+            bars(BarScale::Absolute),
+            [share(0.8), share(0.4), share(0.2)]
+        );
+        // the worst gets none
+        assert_eq!(bars(BarScale::Spread), [share(0.6), share(0.2), 0]);
+    }
+
+    #[test]
+    fn columns_are_right_aligned_after_the_longest_name() {
+        let row = |name: &str, columns: [&str; 2]| Suggestion {
+            columns: columns.map(String::from).to_vec(),
+            ..suggestion(name)
+        };
+        let suggestions = [
+            row("notes.txt", ["file", "12K"]),
+            row("src", ["directory", "4K"]),
+        ];
+        assert_eq!(column_widths(&suggestions, 80), [9, 3]);
+        // trailing columns that don't fit are dropped
+        assert_eq!(column_widths(&suggestions, 12), [9]);
+        assert!(column_widths(&suggestions, 10).is_empty());
+
+        let mut buffer = Vec::new();
+        draw_suggestions(
+            &mut buffer,
+            &suggestions,
+            None,
+            &draw_config(),
+            &mut DrawnRows::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let rows: Vec<String> = output
+            .split(&escapes(cursor::MoveDown(1)))
+            .skip(1)
+            .map(crate::file_manager::strip_ansi)
+            .collect();
+        assert!(rows[0].ends_with("notes.txt       file  12K"));
+        assert!(rows[1].ends_with("src        directory   4K"));
+    }
+
+    #[test]
+    fn stale_match_indices_are_skipped() {
+        // indices of some other text, past the end and inside the two bytes of "é"
+        let sug = Suggestion {
+            match_indices: vec![0, 2, 4, 40, usize::MAX],
+            ..suggestion("café")
+        };
+        let mut buffer = Vec::new();
+        draw_suggestions(
+            &mut buffer,
+            &[sug],
+            None,
+            &draw_config(),
+            &mut DrawnRows::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let green = escapes(SetForegroundColor(Color::Green));
+        assert!(output.contains(&format!("{green}c")));
+        assert!(output.contains(&format!("{green}fé")));
+        assert_eq!(crate::file_manager::strip_ansi(&output).trim(), "café");
+    }
+
+    #[test]
+    fn query_style_covers_only_the_query() {
+        let mut query = QueryInput::default();
+        query.insert_str("ab");
+        let header = |style| {
+            let mut buffer = Vec::new();
+            draw_header(&mut buffer, &query, &[1], None, "fuzzy", 0.0, style).unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        let bold = escapes(SetAttribute(Attribute::Bold));
+        let reset = escapes(SetAttribute(Attribute::Reset));
+        let red = escapes(SetForegroundColor(Color::Red));
+        let output = header(QueryStyle::Bold);
+        let styled = format!(
+            "Search query: {bold}{}a{red}b{reset}",
+            escapes(SetForegroundColor(Color::Reset))
+        );
+        assert!(output.contains(&styled), "{output:?}");
+        // the timing after the reset isn't bold
+        assert_eq!(output.matches(&bold).count(), 1);
+        let output = header(QueryStyle::Color(Color::Cyan));
+        assert!(!output.contains(&bold));
+        let colored = format!("{}a{red}b{reset}", escapes(SetForegroundColor(Color::Cyan)));
+        assert!(output.contains(&colored), "{output:?}");
+    }
+
+    #[test]
+    fn left_truncation_keeps_the_end_and_its_highlights() {
+        let text = "src/deeply/nested/module/main.rs";
+        let main = text.rfind("main").unwrap();
+        let sug = Suggestion {
+            // "s" is in the hidden prefix, the "ma" of "main" is drawn
+            match_indices: vec![0, main, main + 1],
+            ..suggestion(text)
+        };
+        let config = DrawConfig {
+            truncate_left: Some(8),
+            ..draw_config()
+        };
+        // the ellipsis and the last 7 chars
+        assert_eq!(display_width(&sug, &config), 8);
+        let mut buffer = Vec::new();
+        draw_suggestions(
+            &mut buffer,
+            &[sug],
+            None,
+            &config,
+            &mut DrawnRows::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let green = escapes(SetForegroundColor(Color::Green));
+        let reset = escapes(SetForegroundColor(Color::Reset));
+        assert!(
+            output.contains(&format!("{reset}…{green}ma{reset}in.rs")),
+            "{output:?}"
+        );
+        assert!(!output.contains("src/"));
+    }
+
+    #[test]
+    fn hebrew_clusters_are_highlighted_whole() {
+        // shin with its qamats and shin dot, lamed, vav with a holam and final mem
+        let text = "\u{5e9}\u{5b8}\u{5c1}\u{5dc}\u{5d5}\u{5b9}\u{5dd}";
+        let sug = Suggestion {
+            // byte 1 is inside the shin, 2 its qamats and 9 inside the vav
+            match_indices: vec![1, 2, 9],
+            ..suggestion(text)
+        };
+        // the vowel points take no column
+        assert_eq!(display_width(&sug, &draw_config()), 4);
+        let mut buffer = Vec::new();
+        draw_suggestions(
+            &mut buffer,
+            &[sug],
+            None,
+            &draw_config(),
+            &mut DrawnRows::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let green = escapes(SetForegroundColor(Color::Green));
+        let reset = escapes(SetForegroundColor(Color::Reset));
+        let expected = format!(
+            "{green}\u{5e9}\u{5b8}\u{5c1}{reset}\u{5dc}{green}\u{5d5}\u{5b9}{reset}\u{5dd}"
+        );
+        assert!(output.contains(&expected), "{output:?}");
+    }
+
+    #[test]
+    fn contiguous_matches_are_one_highlighted_print() {
+        let runs = highlight_ranges(
+            [
+                (0..1, true),
+                (1..2, true),
+                (2..4, false),
+                (4..5, false),
+                (5..6, true),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(runs, [(0..2, true), (2..5, false), (5..6, true)]);
+
+        let sug = Suggestion {
+            match_indices: vec![2, 3, 4, 5, 9],
+            ..suggestion("my_config.rs")
+        };
+        let mut buffer = Vec::new();
+        draw_suggestions(
+            &mut buffer,
+            &[sug],
+            None,
+            &draw_config(),
+            &mut DrawnRows::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let green = escapes(SetForegroundColor(Color::Green));
+        let reset = escapes(SetForegroundColor(Color::Reset));
+        assert!(
+            output.contains(&format!("{reset}my{green}_con{reset}fig{green}.{reset}rs")),
+            "{output:?}"
+        );
+        assert_eq!(output.matches(&green).count(), 2);
+    }
+}
@@ -1,26 +1,239 @@
 use crate::algorithms;
+use crate::file_manager;
+use crate::structs::FuzzyError;
 use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use serde::Deserialize;
+use std::io::Write;
 
-pub fn get_model() -> TextEmbedding {
-    let model = TextEmbedding::try_new(
-        InitOptions::new(EmbeddingModel::AllMiniLML6V2).with_show_download_progress(true),
+pub const DEFAULT_MODEL: EmbeddingModel = EmbeddingModel::AllMiniLML6V2;
+
+// Settings that change the generated vectors. Batches are embedded one after the other in the
+// EmbedOrder, so with the same model, batch size, max length and a single thread the output is
+// reproducible.
+#[derive(Default)]
+pub struct ModelSettings {
+    // inputs longer than this many tokens are truncated, defaults to the model maximum
+    pub max_length: Option<usize>,
+    // onnx runtime may split float reductions differently across threads
+    pub threads: Option<usize>,
+}
+
+// the order the options are embedded and written in
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbedOrder {
+    // as listed, which for e.g. `find` output can change between runs
+    #[default]
+    File,
+    // sorted by text, so the same options always give the same file
+    Sorted,
+}
+
+impl std::str::FromStr for EmbedOrder {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "file" => Ok(EmbedOrder::File),
+            "sorted" => Ok(EmbedOrder::Sorted),
+            _ => Err(()),
+        }
+    }
+}
+
+// turns a batch of options into vectors, the model or a stand-in in the tests
+pub trait Embedder {
+    fn embed(
+        &mut self,
+        documents: Vec<&str>,
+        batch_size: usize,
+    ) -> Result<Vec<Vec<f32>>, FuzzyError>;
+}
+
+impl Embedder for TextEmbedding {
+    fn embed(
+        &mut self,
+        documents: Vec<&str>,
+        batch_size: usize,
+    ) -> Result<Vec<Vec<f32>>, FuzzyError> {
+        TextEmbedding::embed(self, documents, Some(batch_size))
+            .map_err(|err| FuzzyError::Model(format!("{:#}", err)))
+    }
+}
+
+// the name the model is published under, e.g. "Qdrant/all-MiniLM-L6-v2"
+pub fn model_name() -> String {
+    TextEmbedding::get_model_info(&DEFAULT_MODEL).map_or_else(
+        |_| format!("{:?}", DEFAULT_MODEL),
+        |info| info.model_code.clone(),
     )
-    .unwrap();
-    model
 }
 
-pub fn generate_embeddings(model: &mut TextEmbedding, documents: Vec<&str>) -> Vec<Vec<f32>> {
-    let embeddings = model.embed(documents, None).unwrap();
-    embeddings
+// The progress of a first download is printed to stdout, so it's only shown before the interface
+// takes over the terminal.
+pub fn get_model(
+    settings: &ModelSettings,
+    show_progress: bool,
+) -> Result<TextEmbedding, FuzzyError> {
+    let mut options = InitOptions::new(DEFAULT_MODEL).with_show_download_progress(show_progress);
+    if let Some(max_length) = settings.max_length {
+        options = options.with_max_length(max_length);
+    }
+    if let Some(threads) = settings.threads {
+        options = options.with_intra_threads(threads);
+    }
+    TextEmbedding::try_new(options).map_err(|err| FuzzyError::Model(format!("{:#}", err)))
 }
 
-pub fn generate_embeddings_file(options: &[String]) -> Vec<Vec<f32>> {
+pub fn embed_query(model: &mut dyn Embedder, query: &str) -> Result<Vec<f32>, FuzzyError> {
+    let embeddings = model.embed(vec![query], 1)?;
+    Ok(algorithms::normalized(&embeddings).remove(0))
+}
+
+// embeds and writes one batch at a time so peak memory stays at a single batch
+pub fn generate_embeddings_file(
+    options: &[String],
+    path: &str,
+    batch_size: usize,
+    settings: &ModelSettings,
+    order: EmbedOrder,
+) -> Result<(), FuzzyError> {
     println!("Loading embedding model...");
-    let mut model = get_model();
-    println!("Generating option embeddings...");
-    let mut option_embeddings =
-        generate_embeddings(&mut model, options.iter().map(String::as_str).collect());
-    println!("Normalizing embeddings...");
-    algorithms::normalize_embeddings(&mut option_embeddings);
-    option_embeddings
+    let mut model = get_model(settings, true)?;
+    write_embeddings_file(&mut model, options, path, batch_size, order)
+}
+
+fn write_embeddings_file(
+    embedder: &mut impl Embedder,
+    options: &[String],
+    path: &str,
+    batch_size: usize,
+    order: EmbedOrder,
+) -> Result<(), FuzzyError> {
+    println!("Generating and saving option embeddings...");
+    let mut sorted;
+    let options = match order {
+        EmbedOrder::File => options,
+        EmbedOrder::Sorted => {
+            sorted = options.to_vec();
+            sorted.sort_unstable();
+            &sorted
+        }
+    };
+    let mut file = file_manager::create_embeddings_file(path)?;
+    for batch in options.chunks(batch_size) {
+        let mut batch_embeddings =
+            embedder.embed(batch.iter().map(String::as_str).collect(), batch_size)?;
+        algorithms::normalize_embeddings(&mut batch_embeddings);
+        file_manager::write_embeddings(&mut file, path, batch, &batch_embeddings)?;
+    }
+    file.flush().map_err(|err| FuzzyError::io(path, err))?;
+    println!("Embeddings saved to {}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // a vector from the text alone, recording the batches it was given
+    #[derive(Default)]
+    struct FakeEmbedder {
+        batches: Vec<Vec<String>>,
+    }
+
+    impl Embedder for FakeEmbedder {
+        fn embed(
+            &mut self,
+            documents: Vec<&str>,
+            _batch_size: usize,
+        ) -> Result<Vec<Vec<f32>>, FuzzyError> {
+            self.batches
+                .push(documents.iter().map(|doc| doc.to_string()).collect());
+            Ok(documents
+                .iter()
+                .map(|doc| vec![doc.len() as f32, doc.as_bytes()[0] as f32])
+                .collect())
+        }
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("fuzzyq-{}-{}", std::process::id(), name));
+        path.to_string_lossy().into_owned()
+    }
+
+    // the batches given to the embedder and the file written
+    fn embedded(options: &[&str], order: EmbedOrder, name: &str) -> (Vec<Vec<String>>, String) {
+        let options: Vec<String> = options.iter().map(|opt| opt.to_string()).collect();
+        let path = temp_path(name);
+        let mut embedder = FakeEmbedder::default();
+        write_embeddings_file(&mut embedder, &options, &path, 2, order).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        (embedder.batches, contents)
+    }
+
+    #[test]
+    fn batches_are_embedded_and_written_in_file_order() {
+        let options = ["cherry", "apple", "banana"];
+        let (batches, contents) = embedded(&options, EmbedOrder::File, "file-order.txt");
+        assert_eq!(batches, [vec!["cherry", "apple"], vec!["banana"]]);
+        let keys: Vec<&str> = contents
+            .lines()
+            .map(|line| line.split('\t').next().unwrap())
+            .collect();
+        assert_eq!(keys, options);
+        // the same list gives the same file
+        assert_eq!(
+            embedded(&options, EmbedOrder::File, "file-again.txt").1,
+            contents
+        );
+    }
+
+    #[test]
+    fn sorted_order_gives_one_file_for_any_listing_order() {
+        let (batches, sorted) = embedded(
+            &["cherry", "apple", "banana"],
+            EmbedOrder::Sorted,
+            "sorted.txt",
+        );
+        assert_eq!(batches, [vec!["apple", "banana"], vec!["cherry"]]);
+        let (_, shuffled) = embedded(
+            &["banana", "cherry", "apple"],
+            EmbedOrder::Sorted,
+            "shuffled.txt",
+        );
+        assert_eq!(shuffled, sorted);
+        assert!(matches!("sorted".parse(), Ok(EmbedOrder::Sorted)));
+        assert!("random".parse::<EmbedOrder>().is_err());
+    }
+
+    #[test]
+    #[ignore = "downloads the embedding model"]
+    fn embedding_the_same_options_twice_gives_identical_files() {
+        let options: Vec<String> = [
+            "apple",
+            "banana",
+            "cherry pie",
+            "dragon fruit",
+            "elderberry",
+        ]
+        .map(String::from)
+        .to_vec();
+        let settings = ModelSettings {
+            max_length: None,
+            threads: Some(1),
+        };
+        let paths = ["first.txt", "second.txt"].map(temp_path);
+        for path in &paths {
+            generate_embeddings_file(&options, path, 2, &settings, EmbedOrder::File).unwrap();
+        }
+        let [first, second] = paths.map(|path| {
+            let contents = fs::read(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            contents
+        });
+        assert_eq!(first, second);
+    }
 }
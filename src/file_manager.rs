@@ -1,39 +1,997 @@
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
+use crate::structs::{Candidate, Frecency, FrecencyEntry, FuzzyError, InputFormat, Suggestion};
+use flate2::read::MultiGzDecoder;
+use ignore::WalkBuilder;
+use num_traits::Float;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
 
-pub fn read_file(path: &str) -> Vec<String> {
-    let file = File::open(path).expect("Could not open words.txt");
-    let reader = BufReader::new(file);
-    let sample_options: Vec<String> = reader.lines().filter_map(Result::ok).collect();
-    return sample_options;
+// gzip files are recognized by their magic bytes rather than the extension
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// Opening a FIFO blocks until a writer opens it, and its reads can return fewer bytes than are
+// coming, so the magic bytes are read until there are enough or the input ends. `-` is stdin.
+fn open_text(path: &str) -> Result<Box<dyn BufRead + Send>, FuzzyError> {
+    let mut file: Box<dyn Read + Send> = if path == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(path).map_err(|err| FuzzyError::io(path, err))?)
+    };
+    let mut start = [0; GZIP_MAGIC.len()];
+    let mut filled = 0;
+    while filled < start.len() {
+        match file.read(&mut start[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(FuzzyError::io(path, err)),
+        }
+    }
+    let reader = BufReader::new(io::Cursor::new(start[..filled].to_vec()).chain(file));
+    if start[..filled] == GZIP_MAGIC {
+        // corrupt or truncated data shows up as an error while reading the lines
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+pub fn read_file(path: &str, format: &InputFormat) -> Result<Vec<Candidate>, FuzzyError> {
+    let reader = open_text(path)?;
+    let mut sample_options: Vec<Candidate> = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(|err| FuzzyError::io(path, err))?;
+        sample_options.push(parse_candidate(&line, format));
+    }
+    Ok(sample_options)
 }
 
-pub fn write_embeddings(options: &[String], option_embeddings: Vec<Vec<f32>>, path: &str) {
-    println!("Saving embeddings to file...");
-    let mut file = File::create(path).expect("Could not create embedding file");
+// each option as soon as its line is read, or the error that ended the input
+pub type OptionStream = Receiver<Result<Candidate, FuzzyError>>;
+
+// Reads the options on a background thread, so the search can start while a slow producer (e.g.
+// `find` piped to stdin) is still writing them.
+pub fn stream_file(path: &str, format: InputFormat) -> Result<OptionStream, FuzzyError> {
+    let reader = open_text(path)?;
+    let path = path.to_string();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in reader.lines() {
+            let candidate = line
+                .map(|line| parse_candidate(&line, &format))
+                .map_err(|err| FuzzyError::io(&path, err));
+            let failed = candidate.is_err();
+            // nobody listens anymore once the session has ended
+            if sender.send(candidate).is_err() || failed {
+                return;
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+// Moves the options that arrived since the last call to the end of options and tells whether the
+// input is complete. An error also ends it, the options before it are kept.
+pub fn receive_options(
+    stream: &OptionStream,
+    options: &mut Vec<Candidate>,
+) -> Result<bool, FuzzyError> {
+    loop {
+        match stream.try_recv() {
+            Ok(candidate) => options.push(candidate?),
+            Err(TryRecvError::Empty) => return Ok(false),
+            Err(TryRecvError::Disconnected) => return Ok(true),
+        }
+    }
+}
+
+// A line is `icon\tlabel\tdescription\tkeyword1 keyword2` where the icon, description and
+// keywords are only read when enabled in the format. Otherwise the whole line is the label, tabs
+// included. With columns it's `icon\tlabel\tcolumn1\tcolumn2...` instead.
+fn parse_candidate(line: &str, format: &InputFormat) -> Candidate {
+    let field_count = if format.columns {
+        usize::MAX
+    } else {
+        1 + format.icons as usize + format.descriptions as usize + format.keywords as usize
+    };
+    let mut fields = line.splitn(field_count, '\t');
+    // a line without a tab has no icon rather than an icon and no label
+    let icon = if format.icons && line.contains('\t') {
+        fields.next().map(str::to_string)
+    } else {
+        None
+    };
+    let label = fields.next().unwrap_or_default().to_string();
+    let description = if format.descriptions {
+        fields.next().map(str::to_string)
+    } else {
+        None
+    };
+    let keywords = if format.keywords {
+        fields.next().map_or_else(Vec::new, |field| {
+            field.split_whitespace().map(str::to_string).collect()
+        })
+    } else {
+        Vec::new()
+    };
+    let columns = if format.columns {
+        fields.map(str::to_string).collect()
+    } else {
+        Vec::new()
+    };
+    if !format.raw {
+        return Candidate {
+            icon,
+            columns,
+            ..Candidate::new(label, description, keywords)
+        };
+    }
+    // matched on the plain text, so the escape bytes neither match nor shift the highlights
+    let plain = strip_ansi(&label);
+    let display = (plain != label).then_some(label);
+    Candidate {
+        icon,
+        display,
+        columns: columns.iter().map(|column| strip_ansi(column)).collect(),
+        ..Candidate::new(
+            plain,
+            description.as_deref().map(strip_ansi),
+            keywords.iter().map(|keyword| strip_ansi(keyword)).collect(),
+        )
+    }
+}
+
+// Removes CSI sequences like colors (`ESC [ ... final byte`), OSC sequences like hyperlinks
+// (`ESC ] ... BEL` or `ESC ] ... ESC \`) and other two byte escapes.
+pub fn strip_ansi(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' {
+            plain.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                // parameters and intermediates until the final byte in @ - ~
+                for ch in chars.by_ref() {
+                    if ('@'..='~').contains(&ch) {
+                        break;
+                    }
+                }
+            }
+            Some(']') => {
+                while let Some(ch) = chars.next() {
+                    if ch == '\x07' {
+                        break;
+                    }
+                    if ch == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    plain
+}
+
+// Every file below dir as a candidate, labelled with its path relative to dir. The tree is walked
+// lazily, so only the candidates are held in memory. Entries that can't be read are skipped with a
+// warning rather than aborting the walk.
+pub fn read_dir(dir: &str, respect_ignore: bool) -> Result<Vec<Candidate>, FuzzyError> {
+    fs::metadata(dir).map_err(|err| FuzzyError::io(dir, err))?;
+    // .gitignore, .ignore and hidden files, like other file pickers
+    let walker = WalkBuilder::new(dir)
+        .standard_filters(respect_ignore)
+        .build();
+    let mut candidates = Vec::new();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                eprintln!("Warning: skipping an entry of {}: {}", dir, err);
+                continue;
+            }
+        };
+        if !entry.file_type().is_some_and(|kind| kind.is_file()) {
+            continue;
+        }
+        let path = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        let label = path.to_string_lossy().into_owned();
+        candidates.push(Candidate::new(label, None, Vec::new()));
+    }
+    Ok(candidates)
+}
+
+// one `text\tscore` line per suggestion, in ranked order
+pub fn write_results(path: &str, suggestions: &[Suggestion]) -> Result<(), FuzzyError> {
+    let file = File::create(path).map_err(|err| FuzzyError::io(path, err))?;
+    let mut file = BufWriter::new(file);
+    for sug in suggestions {
+        writeln!(file, "{}\t{}", sug.text, sug.score).map_err(|err| FuzzyError::io(path, err))?;
+    }
+    file.flush().map_err(|err| FuzzyError::io(path, err))
+}
+
+// Frecency lines are `weight\tlast used\ttext`, the text last so it can contain tabs. A missing
+// file is an empty store and lines that don't parse are skipped.
+pub fn read_frecency(path: &Path) -> Result<Frecency, FuzzyError> {
+    let path_str = path.display().to_string();
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Frecency::default()),
+        Err(err) => return Err(FuzzyError::io(&path_str, err)),
+    };
+    let mut frecency = Frecency::default();
+    for line in contents.lines() {
+        let mut fields = line.splitn(3, '\t');
+        if let (Some(weight), Some(last_used), Some(text)) =
+            (fields.next(), fields.next(), fields.next())
+            && let (Ok(weight), Ok(last_used)) = (weight.parse(), last_used.parse())
+        {
+            frecency
+                .entries
+                .insert(text.to_string(), FrecencyEntry { weight, last_used });
+        }
+    }
+    Ok(frecency)
+}
+
+pub fn write_frecency(path: &Path, frecency: &Frecency) -> Result<(), FuzzyError> {
+    let path_str = path.display().to_string();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| FuzzyError::io(&path_str, err))?;
+    }
+    let file = File::create(path).map_err(|err| FuzzyError::io(&path_str, err))?;
+    let mut file = BufWriter::new(file);
+    for (text, entry) in &frecency.entries {
+        writeln!(file, "{}\t{}\t{}", entry.weight, entry.last_used, text)
+            .map_err(|err| FuzzyError::io(&path_str, err))?;
+    }
+    file.flush().map_err(|err| FuzzyError::io(&path_str, err))
+}
+
+// The selection followed by the terminator, or nothing when the session ended without one, so a
+// reader waiting on a FIFO always sees the end of the output.
+pub fn write_selection(
+    path: &str,
+    selection: Option<&str>,
+    terminator: char,
+) -> Result<(), FuzzyError> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .map_err(|err| FuzzyError::io(path, err))?;
+    if let Some(text) = selection {
+        write!(file, "{}{}", text, terminator).map_err(|err| FuzzyError::io(path, err))?;
+    }
+    file.flush().map_err(|err| FuzzyError::io(path, err))
+}
+
+// appends to an existing log so several sessions can be analyzed together
+pub fn open_timing_log(path: &str) -> Result<BufWriter<File>, FuzzyError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| FuzzyError::io(path, err))?;
+    Ok(BufWriter::new(file))
+}
+
+// one `milliseconds\tquery chars\tresults` line per search
+pub fn write_timing(
+    log: &mut impl Write,
+    path: &str,
+    delta_time: f64,
+    query: &str,
+    result_count: usize,
+) -> Result<(), FuzzyError> {
+    writeln!(
+        log,
+        "{:.3}\t{}\t{}",
+        delta_time * 1000.0,
+        query.chars().count(),
+        result_count
+    )
+    .map_err(|err| FuzzyError::io(path, err))
+}
+
+pub fn create_embeddings_file(path: &str) -> Result<BufWriter<File>, FuzzyError> {
+    let file = File::create(path).map_err(|err| FuzzyError::io(path, err))?;
+    Ok(BufWriter::new(file))
+}
+
+// Embedding lines are `option\tv1,v2,...`. Backslashes, tabs and newlines in the option are
+// escaped as `\\`, `\t` and `\n`, so the first raw tab always ends the option.
+fn escape_key(key: &str) -> String {
+    let mut escaped = String::with_capacity(key.len());
+    for ch in key.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn unescape_key(key: &str) -> String {
+    let mut unescaped = String::with_capacity(key.len());
+    let mut chars = key.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            // unknown escapes are kept as they are
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+// appends one line per option, so batches can be written as soon as they are embedded
+pub fn write_embeddings(
+    file: &mut impl Write,
+    path: &str,
+    options: &[String],
+    option_embeddings: &[Vec<impl Display>],
+) -> Result<(), FuzzyError> {
     for (opt, emb) in options.iter().zip(option_embeddings.iter()) {
         let emb_str: Vec<String> = emb.iter().map(|v| v.to_string()).collect();
-        let line = format!("{}\t{}\n", opt, emb_str.join(","));
+        let line = format!("{}\t{}\n", escape_key(opt), emb_str.join(","));
         file.write_all(line.as_bytes())
-            .expect("Could not write to embedding file");
+            .map_err(|err| FuzzyError::io(path, err))?;
     }
-    println!("Embeddings saved to {}", path);
+    Ok(())
 }
 
-pub fn read_embeddings_file(path: &str) -> io::Result<Vec<(String, Vec<f32>)>> {
-    let file = File::open(path)?;
+// Values are joined by commas, as written by write_embeddings, or by whitespace as some other tools
+// export them, and may be padded with spaces (e.g. `0.1, -1.2e-3`).
+fn embedding_values(emb_str: &str) -> Box<dyn Iterator<Item = &str> + '_> {
+    if emb_str.contains(',') {
+        Box::new(emb_str.split(',').map(str::trim))
+    } else {
+        Box::new(emb_str.split_whitespace())
+    }
+}
+
+// each option with its embedding, in file order
+pub type EmbeddingRows<T> = Vec<(String, Vec<T>)>;
+
+// Generic over the float type like the embedding math, callers pick f32 or f64. Besides the rows
+// it returns their dimension, None for a file without any.
+pub fn read_embeddings_file<T: Float + FromStr>(
+    path: &str,
+) -> Result<(EmbeddingRows<T>, Option<usize>), FuzzyError> {
+    let file = File::open(path).map_err(|err| FuzzyError::io(path, err))?;
     let reader = BufReader::new(file);
     let mut embeddings = Vec::new();
-    for line in reader.lines() {
-        let line = line?;
-        let mut parts = line.splitn(2, '\t');
-        if let (Some(opt), Some(emb_str)) = (parts.next(), parts.next()) {
-            let emb: Vec<f32> = emb_str
-                .split(',')
-                .filter_map(|s| s.parse::<f32>().ok())
+    // the first valid row determines the dimension every other row must have
+    let mut dimension: Option<usize> = None;
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| FuzzyError::io(path, err))?;
+        if let Some(row) = parse_embedding_row(&line, line_number, path, &mut dimension) {
+            embeddings.push(row);
+        }
+    }
+    Ok((embeddings, dimension))
+}
+
+// One line of an embeddings file, None with a warning when it's malformed or has another
+// dimension than the rows before it. Lines without a tab are skipped silently.
+fn parse_embedding_row<T: Float + FromStr>(
+    line: &str,
+    line_number: usize,
+    path: &str,
+    dimension: &mut Option<usize>,
+) -> Option<(String, Vec<T>)> {
+    let (opt, emb_str) = line.split_once('\t')?;
+    let opt = unescape_key(opt);
+    let emb: Result<Vec<T>, &str> = embedding_values(emb_str)
+        .map(|s| s.parse::<T>().map_err(|_| s))
+        .collect();
+    let emb = match emb {
+        Ok(emb) if !emb.is_empty() => emb,
+        Ok(_) => {
+            eprintln!(
+                "Warning: skipping '{}' on line {} of {}: empty embedding",
+                opt,
+                line_number + 1,
+                path
+            );
+            return None;
+        }
+        Err(value) => {
+            eprintln!(
+                "Warning: skipping '{}' on line {} of {}: invalid float '{}' in embedding",
+                opt,
+                line_number + 1,
+                path,
+                value
+            );
+            return None;
+        }
+    };
+    let expected = *dimension.get_or_insert(emb.len());
+    if emb.len() != expected {
+        eprintln!(
+            "Warning: skipping '{}' on line {} of {}: expected {} values, found {}",
+            opt,
+            line_number + 1,
+            path,
+            expected,
+            emb.len()
+        );
+        return None;
+    }
+    Some((opt, emb))
+}
+
+// Where each row of an embeddings file starts, so the rows of a few options can be read without
+// parsing or holding the vectors of all the others. Only the first valid row is parsed, for the
+// dimension.
+pub struct EmbeddingIndex {
+    path: String,
+    // the byte offset and line number of the row of each option
+    rows: HashMap<String, (u64, usize)>,
+    pub dimension: Option<usize>,
+}
+
+impl EmbeddingIndex {
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    // The rows of the given options that are in the file, in the order asked for. Malformed rows
+    // are skipped with the warnings of read_embeddings_file.
+    pub fn read_rows(&self, options: &[&str]) -> Result<EmbeddingRows<f32>, FuzzyError> {
+        let path = self.path.as_str();
+        let file = File::open(path).map_err(|err| FuzzyError::io(path, err))?;
+        let mut reader = BufReader::new(file);
+        let mut dimension = self.dimension;
+        let mut rows = Vec::new();
+        let mut line = String::new();
+        for opt in options {
+            let Some(&(offset, line_number)) = self.rows.get(*opt) else {
+                continue;
+            };
+            line.clear();
+            reader
+                .seek(SeekFrom::Start(offset))
+                .and_then(|_| reader.read_line(&mut line))
+                .map_err(|err| FuzzyError::io(path, err))?;
+            let text = without_line_ending(&line);
+            if let Some(row) = parse_embedding_row(text, line_number, path, &mut dimension) {
+                rows.push(row);
+            }
+        }
+        Ok(rows)
+    }
+}
+
+// what BufRead::lines strips from a line read with read_line
+fn without_line_ending(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+pub fn index_embeddings_file(path: &str) -> Result<EmbeddingIndex, FuzzyError> {
+    let file = File::open(path).map_err(|err| FuzzyError::io(path, err))?;
+    let mut reader = BufReader::new(file);
+    let mut rows = HashMap::new();
+    let mut dimension: Option<usize> = None;
+    let mut offset = 0;
+    let mut line = String::new();
+    for line_number in 0.. {
+        line.clear();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|err| FuzzyError::io(path, err))?;
+        if read == 0 {
+            break;
+        }
+        let text = without_line_ending(&line);
+        if dimension.is_none() {
+            parse_embedding_row::<f32>(text, line_number, path, &mut dimension);
+        }
+        if let Some((opt, _)) = text.split_once('\t') {
+            rows.insert(unescape_key(opt), (offset, line_number));
+        }
+        offset += read as u64;
+    }
+    Ok(EmbeddingIndex {
+        path: path.to_string(),
+        rows,
+        dimension,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a file in the temp dir, unique to the test that writes it
+    fn temp_file(name: &str, contents: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("fuzzyq-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn malformed_embedding_rows_are_skipped() {
+        let path = temp_file(
+            "malformed.txt",
+            b"apple\t0.1,0.2,0.3\nbad\t0.1,x,0.3\nempty\t\nshort\t0.1,0.2\nno tab\npear\t1,2,3\n",
+        );
+        let (rows, dimension) = read_embeddings_file::<f32>(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(dimension, Some(3));
+        let keys: Vec<&str> = rows.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["apple", "pear"]);
+        assert_eq!(rows[1].1, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn indexed_rows_are_read_like_the_whole_file() {
+        let path = temp_file(
+            "indexed.txt",
+            b"apple\t0.1,0.2,0.3\r\nbad\t0.1,x,0.3\ntab\\tkey\t1 2 3\npear\t4,5,6\n",
+        );
+        let index = index_embeddings_file(&path).unwrap();
+        let (whole, _) = read_embeddings_file::<f32>(&path).unwrap();
+        let rows = index
+            .read_rows(&["pear", "bad", "missing", "apple"])
+            .unwrap();
+        let keyed = index.read_rows(&["tab\tkey"]).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(index.dimension, Some(3));
+        assert_eq!(index.len(), 4);
+        // in the order asked for, the malformed and unknown ones left out
+        assert_eq!(rows, [whole[2].clone(), whole[0].clone()]);
+        assert_eq!(keyed, [whole[1].clone()]);
+    }
+
+    #[test]
+    fn embeddings_written_in_batches_read_back_in_order() {
+        let options: Vec<String> = ["apple", "tab\tkey", "back\\slash", "new\nline", "pear"]
+            .map(String::from)
+            .to_vec();
+        let embeddings: Vec<Vec<f32>> = (0..options.len())
+            .map(|i| vec![i as f32, 0.5, -1.25])
+            .collect();
+        let write = |name: &str, batch_size: usize| {
+            let path = temp_file(name, b"");
+            let mut file = create_embeddings_file(&path).unwrap();
+            for (batch, batch_embeddings) in options
+                .chunks(batch_size)
+                .zip(embeddings.chunks(batch_size))
+            {
+                write_embeddings(&mut file, &path, batch, batch_embeddings).unwrap();
+            }
+            file.flush().unwrap();
+            drop(file);
+            let contents = fs::read(&path).unwrap();
+            let rows = read_embeddings_file::<f32>(&path).unwrap().0;
+            fs::remove_file(&path).unwrap();
+            (contents, rows)
+        };
+        let (whole, rows) = write("whole.txt", options.len());
+        assert_eq!(write("batched.txt", 2).0, whole);
+        assert_eq!(write("single.txt", 1).0, whole);
+        let expected: Vec<(String, Vec<f32>)> = options.into_iter().zip(embeddings).collect();
+        assert_eq!(rows, expected);
+    }
+
+    #[test]
+    fn keywords_follow_the_label() {
+        let format = InputFormat {
+            keywords: true,
+            ..InputFormat::default()
+        };
+        let candidate = parse_candidate("Firefox\tbrowser  web", &format);
+        assert_eq!(candidate.text, "Firefox");
+        assert_eq!(candidate.keywords, ["browser", "web"]);
+        assert_eq!(
+            parse_candidate("Files", &format).keywords,
+            Vec::<String>::new()
+        );
+        // without the format the tab is part of the label
+        let plain = parse_candidate("Firefox\tbrowser", &InputFormat::default());
+        assert_eq!(plain.text, "Firefox\tbrowser");
+        assert!(plain.keywords.is_empty());
+    }
+
+    #[test]
+    fn keys_with_tabs_round_trip() {
+        for key in [
+            "tab\tkey",
+            "tab\t",
+            "\\t literally",
+            "line\nbreak",
+            "trailing\\",
+        ] {
+            assert_eq!(unescape_key(&escape_key(key)), key);
+            assert!(!escape_key(key).contains(['\t', '\n']));
+        }
+        // unknown escapes and a lone backslash are kept
+        assert_eq!(unescape_key("a\\x"), "a\\x");
+        assert_eq!(unescape_key("end\\"), "end\\");
+        let path = temp_file("tabs.txt", b"");
+        let mut file = create_embeddings_file(&path).unwrap();
+        write_embeddings(
+            &mut file,
+            &path,
+            &["tab\tkey".to_string()],
+            &[vec![0.5f32, 1.0]],
+        )
+        .unwrap();
+        drop(file);
+        let rows = read_embeddings_file::<f32>(&path).unwrap().0;
+        fs::remove_file(&path).unwrap();
+        assert_eq!(rows, [("tab\tkey".to_string(), vec![0.5, 1.0])]);
+    }
+
+    #[test]
+    fn failing_to_create_a_file_is_an_error() {
+        let path = std::env::temp_dir()
+            .join("fuzzyq-missing-dir")
+            .join("embeddings.txt");
+        let path = path.to_string_lossy();
+        let err = create_embeddings_file(&path).err().unwrap();
+        assert!(matches!(&err, FuzzyError::Io { path: failed, .. } if *failed == path));
+        assert!(read_embeddings_file::<f32>(&path).is_err());
+        assert!(write_results(&path, &[]).is_err());
+    }
+
+    #[test]
+    fn results_are_exported_in_ranked_order() {
+        let suggestions = [("apple", 620), ("application", 540)].map(|(text, score)| Suggestion {
+            text: text.to_string(),
+            score,
+            ..Default::default()
+        });
+        let path = temp_file("results.txt", b"old contents\n");
+        write_results(&path, &suggestions).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "apple\t620\napplication\t540\n");
+    }
+
+    #[test]
+    fn icons_are_split_off_the_label() {
+        let format = InputFormat {
+            icons: true,
+            ..InputFormat::default()
+        };
+        let candidate = parse_candidate("📁\tDocuments", &format);
+        assert_eq!(candidate.icon.as_deref(), Some("📁"));
+        assert_eq!(candidate.text, "Documents");
+        // a line without a tab is only a label
+        let candidate = parse_candidate("Downloads", &format);
+        assert_eq!(
+            (candidate.icon, candidate.text.as_str()),
+            (None, "Downloads")
+        );
+    }
+
+    #[test]
+    fn neighbors_are_ranked_from_an_embeddings_file() {
+        let path = temp_file(
+            "neighbors.txt",
+            b"king\t1,0,0\nqueen\t0.9,0.1,0\nprince\t0.7,0.7,0\napple\t0,0,1\n",
+        );
+        let (embeddings, dimension) = read_embeddings_file::<f32>(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(dimension, Some(3));
+        let neighbors =
+            crate::algorithms::nearest_neighbors("king", &[1.0, 0.0, 0.0], &embeddings, 2);
+        let words: Vec<&str> = neighbors.iter().map(|(word, _)| *word).collect();
+        assert_eq!(words, ["queen", "prince"]);
+        // a word that isn't in the file keeps every entry as a neighbor
+        let neighbors =
+            crate::algorithms::nearest_neighbors("crown", &[1.0, 0.0, 0.0], &embeddings, 10);
+        assert_eq!(neighbors.len(), 4);
+        assert_eq!(neighbors[0], ("king", 1.0));
+    }
+
+    #[test]
+    fn gzipped_word_lists_read_like_plain_ones() {
+        let text = "apple\nbanana split\ncafé\n";
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        // recognized by the magic bytes, not the name
+        let plain_path = temp_file("words.txt", text.as_bytes());
+        let gzip_path = temp_file("words.dat", &gzipped);
+        let texts = |path: &str| -> Vec<String> {
+            read_file(path, &InputFormat::default())
+                .unwrap()
+                .into_iter()
+                .map(|candidate| candidate.text)
+                .collect()
+        };
+        assert_eq!(texts(&gzip_path), texts(&plain_path));
+        assert_eq!(texts(&gzip_path), ["apple", "banana split", "café"]);
+        // cut off halfway, an error rather than the lines read until then
+        let truncated_path = temp_file("truncated.gz", &gzipped[..gzipped.len() / 2]);
+        let truncated = read_file(&truncated_path, &InputFormat::default());
+        for path in [plain_path, gzip_path, truncated_path] {
+            fs::remove_file(path).unwrap();
+        }
+        assert!(matches!(truncated, Err(FuzzyError::Io { .. })));
+    }
+
+    #[test]
+    fn timing_log_lines_are_well_formed_and_appended() {
+        let path = temp_file("timing.log", b"");
+        for session in [
+            [(0.0123, "ab"), (0.0004567, "abç")],
+            [(1.5, ""), (0.001, "a")],
+        ] {
+            let mut log = open_timing_log(&path).unwrap();
+            for (delta_time, query) in session {
+                write_timing(&mut log, &path, delta_time, query, 7).unwrap();
+            }
+            log.flush().unwrap();
+        }
+        let log = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            log,
+            "12.300\t2\t7\n0.457\t3\t7\n1500.000\t0\t7\n1.000\t1\t7\n"
+        );
+    }
+
+    #[test]
+    fn f64_embeddings_keep_their_precision() {
+        let path = temp_file("precise.txt", b"a\t0.123456789012,1\nb\t1,0.123456789012\n");
+        let (wide, _) = read_embeddings_file::<f64>(&path).unwrap();
+        let (narrow, _) = read_embeddings_file::<f32>(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(wide[0].1, [0.123456789012, 1.0]);
+        assert_eq!(narrow[0].1, [0.123456789012f64 as f32, 1.0]);
+        let similarity = |a: f64, b: f64| 2.0 * a * b / (a * a + b * b);
+        let wide_cosine = crate::algorithms::cosine_similarity_full(&wide[0].1, &wide[1].1);
+        assert!((wide_cosine - similarity(0.123456789012, 1.0)).abs() < 1e-15);
+        // the f32 path is unchanged, as close as f32 gets
+        let narrow_cosine = crate::algorithms::cosine_similarity_full(&narrow[0].1, &narrow[1].1);
+        assert!((narrow_cosine as f64 - wide_cosine).abs() < 1e-6);
+    }
+
+    #[test]
+    fn directories_list_their_files_relative_to_the_root() {
+        let root = std::env::temp_dir().join(format!("fuzzyq-{}-tree", std::process::id()));
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        for file in [
+            "README.md",
+            "src/main.rs",
+            "src/nested/deep.rs",
+            "target/out",
+            ".hidden",
+        ] {
+            fs::write(root.join(file), b"").unwrap();
+        }
+        fs::write(root.join(".ignore"), b"target/\n").unwrap();
+        let dir = root.to_string_lossy();
+        let paths = |respect_ignore: bool| -> Vec<String> {
+            let mut paths: Vec<String> = read_dir(&dir, respect_ignore)
+                .unwrap()
+                .into_iter()
+                .map(|candidate| candidate.text.replace('\\', "/"))
                 .collect();
-            embeddings.push((opt.to_string(), emb));
+            paths.sort();
+            paths
+        };
+        let filtered = paths(true);
+        let everything = paths(false);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(filtered, ["README.md", "src/main.rs", "src/nested/deep.rs"]);
+        assert_eq!(
+            everything,
+            [
+                ".hidden",
+                ".ignore",
+                "README.md",
+                "src/main.rs",
+                "src/nested/deep.rs",
+                "target/out"
+            ]
+        );
+        assert!(read_dir(&dir, true).is_err());
+    }
+
+    #[test]
+    fn frecency_store_round_trips() {
+        let path =
+            std::env::temp_dir().join(format!("fuzzyq-{}-frecency/store", std::process::id()));
+        // a missing store is empty rather than an error
+        assert!(read_frecency(&path).unwrap().entries.is_empty());
+        let mut frecency = Frecency::default();
+        frecency.record("with\ttab", 100);
+        frecency.record("plain", 200);
+        frecency.record("plain", 200);
+        write_frecency(&path, &frecency).unwrap();
+        let mut contents = fs::read_to_string(&path).unwrap();
+        contents.push_str("not a number\t5\tbad\n");
+        fs::write(&path, contents).unwrap();
+        let read = read_frecency(&path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(read.entries.len(), 2);
+        assert_eq!(read.entries["with\ttab"].last_used, 100);
+        assert_eq!(read.entries["plain"].weight, 2.0);
+    }
+
+    #[test]
+    fn raw_lines_match_on_the_text_without_escapes() {
+        let format = InputFormat {
+            raw: true,
+            ..InputFormat::default()
+        };
+        // like grep --color=always
+        let colored = "src/main.rs:\x1b[01;31m\x1b[Kfn main\x1b[m\x1b[K() {";
+        let candidate = parse_candidate(colored, &format);
+        assert_eq!(candidate.text, "src/main.rs:fn main() {");
+        assert_eq!(candidate.display.as_deref(), Some(colored));
+        let config = crate::structs::ScoringConfig::default();
+        let sug = crate::algorithms::fuzzy_match("main", &candidate.text, &config).unwrap();
+        assert_eq!(sug.match_indices, [4, 5, 6, 7]);
+        // uncolored lines have nothing else to display
+        assert_eq!(parse_candidate("plain", &format).display, None);
+        // hyperlinks, with either terminator
+        let link = "\x1b]8;;https://example.com\x07docs\x1b]8;;\x1b\\";
+        assert_eq!(strip_ansi(link), "docs");
+    }
+
+    #[test]
+    fn selection_is_written_with_its_terminator() {
+        let path = temp_file("selection", b"left over from before");
+        write_selection(&path, Some("a file\nname"), '\0').unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"a file\nname\0");
+        // no selection leaves the file empty
+        write_selection(&path, None, '\n').unwrap();
+        assert!(fs::read(&path).unwrap().is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn options_and_selection_round_trip_over_fifos() {
+        let fifo = |name: &str| {
+            let path = std::env::temp_dir().join(format!("fuzzyq-{}-{}", std::process::id(), name));
+            let made = std::process::Command::new("mkfifo")
+                .arg(&path)
+                .status()
+                .unwrap();
+            assert!(made.success());
+            path.to_string_lossy().into_owned()
+        };
+        let words = fifo("words.fifo");
+        let selection = fifo("selection.fifo");
+        // each open blocks until the other end is opened too
+        let writer = {
+            let words = words.clone();
+            thread::spawn(move || {
+                let mut file = OpenOptions::new().write(true).open(&words).unwrap();
+                // in pieces, so the reader sees partial reads
+                for piece in ["ap", "ple\nban", "ana\n"] {
+                    file.write_all(piece.as_bytes()).unwrap();
+                    file.flush().unwrap();
+                }
+            })
+        };
+        let options = read_file(&words, &InputFormat::default()).unwrap();
+        writer.join().unwrap();
+        let texts: Vec<&str> = options.iter().map(|opt| opt.text.as_str()).collect();
+        assert_eq!(texts, ["apple", "banana"]);
+
+        let reader = {
+            let selection = selection.clone();
+            thread::spawn(move || fs::read(&selection).unwrap())
+        };
+        write_selection(&selection, Some("banana"), '\n').unwrap();
+        assert_eq!(reader.join().unwrap(), b"banana\n");
+        for path in [words, selection] {
+            fs::remove_file(path).unwrap();
         }
     }
-    Ok(embeddings)
+
+    #[test]
+    fn dimension_is_detected_from_the_first_row() {
+        let path = temp_file("dimension.txt", b"a\t1,0,0\nb\t0,1\nc\t0,0,1\n");
+        let (rows, dimension) = read_embeddings_file::<f32>(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(dimension, Some(3));
+        // rows of another length are skipped
+        let keys: Vec<&str> = rows.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["a", "c"]);
+        let empty = temp_file("no-rows.txt", b"");
+        let (_, dimension) = read_embeddings_file::<f32>(&empty).unwrap();
+        fs::remove_file(&empty).unwrap();
+        assert_eq!(dimension, None);
+    }
+
+    #[test]
+    fn streamed_options_are_appended_and_ranked_as_they_arrive() {
+        use crate::algorithms::FuzzyScorer;
+        use crate::structs::{ScoringConfig, SearchConfig};
+        use fuzzyq::search::get_suggestions;
+
+        let scoring = ScoringConfig::default();
+        let scorer = FuzzyScorer { config: &scoring };
+        let config = SearchConfig {
+            limit: 10,
+            ..Default::default()
+        };
+        let ranked = |options: &[Candidate]| -> Vec<String> {
+            get_suggestions("main", options, &scorer, &config)
+                .suggestions
+                .into_iter()
+                .map(|sug| sug.text)
+                .collect()
+        };
+        let send = |sender: &mpsc::Sender<_>, text: &str| {
+            let candidate = Candidate::new(text.to_string(), None, Vec::new());
+            sender.send(Ok(candidate)).unwrap();
+        };
+
+        let (sender, stream) = mpsc::channel();
+        let mut options = Vec::new();
+        assert!(!receive_options(&stream, &mut options).unwrap());
+        send(&sender, "src/lib.rs");
+        send(&sender, "src/domain.rs");
+        assert!(!receive_options(&stream, &mut options).unwrap());
+        assert_eq!(ranked(&options), ["src/domain.rs"]);
+        // a better match arriving later is ranked above the earlier ones
+        send(&sender, "main.rs");
+        assert!(!receive_options(&stream, &mut options).unwrap());
+        assert_eq!(options.len(), 3);
+        assert_eq!(ranked(&options), ["main.rs", "src/domain.rs"]);
+        drop(sender);
+        assert!(receive_options(&stream, &mut options).unwrap());
+
+        // an error ends the input, the options before it stay
+        let (sender, stream) = mpsc::channel();
+        send(&sender, "main.py");
+        let err = io::Error::new(io::ErrorKind::InvalidData, "not utf-8");
+        sender.send(Err(FuzzyError::io("-", err))).unwrap();
+        assert!(receive_options(&stream, &mut options).is_err());
+        assert_eq!(options.len(), 4);
+    }
+
+    #[test]
+    fn padded_and_whitespace_joined_values_are_read() {
+        let path = temp_file(
+            "padded.txt",
+            b"comma\t 1.5e-3 , -2E2,0.25 \nspaced\t1e0  -3.5e-1\t4\nbad\t1.0, 2.0e, 3.0\n",
+        );
+        let (rows, dimension) = read_embeddings_file::<f32>(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(dimension, Some(3));
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], ("comma".to_string(), vec![1.5e-3, -200.0, 0.25]));
+        // any whitespace joins the values when there are no commas, tabs too
+        assert_eq!(rows[1], ("spaced".to_string(), vec![1.0, -0.35, 4.0]));
+        let values: Vec<&str> = embedding_values(" 1 ,2,  3").collect();
+        assert_eq!(values, ["1", "2", "3"]);
+    }
 }
@@ -0,0 +1,7 @@
+// The matching core, without the terminal interface and the embedding model, so it also builds
+// for wasm32-unknown-unknown with `--no-default-features`.
+pub mod algorithms;
+pub mod profile;
+pub mod search;
+pub mod session;
+pub mod structs;
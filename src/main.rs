@@ -1,115 +1,1224 @@
-mod algorithms;
+mod cli;
+mod config;
 mod draw;
 mod embedder;
 mod file_manager;
-mod structs;
+mod output;
+mod preview;
 
-use crate::structs::Suggestion;
+use fuzzyq::{algorithms, profile, structs};
+
+use crate::algorithms::{CompositeScorer, EditDistanceScorer, FuzzyScorer, SemanticScorer};
+use crate::cli::Args;
+use crate::draw::DrawnRows;
+use crate::embedder::Embedder;
+use crate::file_manager::EmbeddingIndex;
+use crate::output::ResultFormat;
 use crate::structs::terminal_guard::TerminalGuard;
+use crate::structs::{
+    Candidate, DrawConfig, FuzzyError, QueryInput, SearchConfig, SearchMode, Suggestion,
+};
+use fuzzyq::search::{SearchResults, best_candidates, get_suggestions};
 
-use fastembed::TextEmbedding;
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
 
 use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::terminal;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::io::{self, Write};
-use std::time::Instant;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
-fn get_fuzzy_suggestions(query: &str, options: &[String]) -> Vec<Suggestion> {
-    let mut suggestions: Vec<Suggestion> = options
-        .iter()
-        .filter_map(|opt| algorithms::fuzzy_match(query, opt))
-        .collect();
+// how often options streamed from stdin are taken in and searched
+const STREAM_REFRESH: Duration = Duration::from_millis(100);
+
+type EmbeddingMap = HashMap<String, Vec<f32>>;
+
+// the embeddings file as read for semantic search
+struct StoredEmbeddings {
+    embeddings: EmbeddingMap,
+    // with --semantic-rerank only where the rows are, each is read once its option is reranked
+    index: Option<EmbeddingIndex>,
+    // the length of the stored embeddings, None when there are none
+    dimension: Option<usize>,
+}
+
+// everything semantic search needs, loaded with --semantic or on the first switch to it
+struct SemanticSearch {
+    model: Box<dyn Embedder>,
+    embeddings: EmbeddingMap,
+    // the rows of the embeddings file that are only read when they are needed
+    index: Option<EmbeddingIndex>,
+    // whether the loaded embeddings have unit length
+    normalized: bool,
+    // the length of the stored embeddings, None when there are none
+    dimension: Option<usize>,
+}
+
+// the embedding model, a download can fail e.g. without a network
+type ModelResult = Result<Box<dyn Embedder>, FuzzyError>;
+
+fn load_semantic(
+    stored: StoredEmbeddings,
+    load_model: impl FnOnce() -> ModelResult,
+) -> Result<SemanticSearch, FuzzyError> {
+    Ok(SemanticSearch {
+        model: load_model()?,
+        normalized: algorithms::embeddings_normalized(stored.embeddings.values()),
+        embeddings: stored.embeddings,
+        index: stored.index,
+        dimension: stored.dimension,
+    })
+}
+
+// Semantic search for --semantic, set up before the interface starts. Lexical search still works
+// without the model, e.g. when it can't be downloaded, then it's None and the header notice says
+// so.
+fn start_semantic(
+    stored: StoredEmbeddings,
+    args: &Args,
+    load_model: impl FnOnce() -> ModelResult,
+) -> (Option<SemanticSearch>, Option<String>) {
+    match load_semantic(stored, load_model) {
+        Ok(loaded) => {
+            if !args.quiet {
+                let model = embedder::model_name();
+                let indexed = loaded.index.as_ref().map_or(0, EmbeddingIndex::len);
+                let count = loaded.embeddings.len() + indexed;
+                eprintln!("{}", semantic_summary(&model, loaded.dimension, count));
+            }
+            (Some(loaded), None)
+        }
+        Err(err) => {
+            eprintln!("Warning: {}, falling back to lexical search", err);
+            let notice = "semantic search unavailable, using lexical".to_string();
+            (None, Some(notice))
+        }
+    }
+}
+
+// the options without a stored embedding, which are ranked lexically, unless reranking embeds
+// them when they first match
+fn stale_embeddings(
+    options: &[Candidate],
+    stored: &EmbeddingMap,
+    path: &str,
+    args: &Args,
+) -> Option<String> {
+    let missing_count = missing_embeddings(options, stored).len();
+    (missing_count > 0 && args.semantic_rerank.is_none()).then(|| {
+        format!(
+            "{} is stale, {} options have no embedding and will be ranked lexically",
+            path, missing_count
+        )
+    })
+}
+
+// what Ctrl+T leads to
+struct Toggled {
+    mode: SearchMode,
+    // shown in the header, why semantic search is unavailable
+    status: Option<String>,
+    // shown in the header for the rest of the session, e.g. that the embeddings are stale
+    notice: Option<String>,
+    // the query is ranked again in the new mode
+    search: bool,
+}
+
+// Switches between lexical and semantic search. The embeddings and the model are only loaded on
+// the first switch to semantic search, with the same stale embeddings check as --semantic.
+fn toggle_mode(
+    mode: SearchMode,
+    semantic: &mut Option<SemanticSearch>,
+    options: &[Candidate],
+    embeddings_path: &str,
+    args: &Args,
+    load_model: impl FnOnce() -> ModelResult,
+) -> Toggled {
+    let unchanged = |status: String| Toggled {
+        mode,
+        status: Some(status),
+        notice: None,
+        search: false,
+    };
+    if args.max_edit_distance.is_some() {
+        return unchanged("--max-edit-distance has no semantic mode".to_string());
+    }
+    let mut notice = None;
+    if mode == SearchMode::Lexical && semantic.is_none() {
+        let loaded = read_option_embeddings(embeddings_path, args.semantic_rerank.is_some())
+            .and_then(|stored| {
+                notice = stale_embeddings(options, &stored.embeddings, embeddings_path, args);
+                load_semantic(stored, load_model)
+            });
+        match loaded {
+            Ok(loaded) => *semantic = Some(loaded),
+            Err(err) => return unchanged(format!("semantic search unavailable: {}", err)),
+        }
+    }
+    Toggled {
+        mode: mode.toggled(),
+        status: None,
+        notice,
+        search: true,
+    }
+}
+
+// the setup line printed when semantic search starts
+fn semantic_summary(model: &str, dimension: Option<usize>, count: usize) -> String {
+    match dimension {
+        Some(dimension) => format!(
+            "Semantic search: {}, {} dimensions, {} embeddings loaded",
+            model, dimension, count
+        ),
+        None => format!("Semantic search: {}, no embeddings loaded", model),
+    }
+}
 
-    suggestions.sort_by(|a, b| b.score.cmp(&a.score));
-    suggestions
+// With on_demand only the reranked options need an embedding: the file is indexed and its rows
+// are read when their option is first reranked, the others are embedded then. So there may be no
+// file at all.
+fn read_option_embeddings(path: &str, on_demand: bool) -> Result<StoredEmbeddings, FuzzyError> {
+    if on_demand {
+        let index = Path::new(path)
+            .exists()
+            .then(|| file_manager::index_embeddings_file(path))
+            .transpose()?;
+        return Ok(StoredEmbeddings {
+            embeddings: HashMap::new(),
+            dimension: index.as_ref().and_then(|index| index.dimension),
+            index,
+        });
+    }
+    let (embeddings, dimension) = file_manager::read_embeddings_file(path)?;
+    Ok(StoredEmbeddings {
+        embeddings: embeddings.into_iter().collect(),
+        index: None,
+        dimension,
+    })
+}
+
+// cosines between vectors of different lengths are meaningless, e.g. after switching models
+fn check_dimension(expected: Option<usize>, found: usize) -> Result<(), FuzzyError> {
+    match expected {
+        Some(expected) if expected != found => {
+            Err(FuzzyError::DimensionMismatch { expected, found })
+        }
+        _ => Ok(()),
+    }
 }
 
-fn get_semantic_suggestions(
+// ranks the options lexically, or blended with the semantic ranking when it is loaded
+fn search(
     query: &str,
-    option_embeddings: &[(String, Vec<f32>)],
-    query_embedding: &Vec<f32>,
-) -> Vec<Suggestion> {
-    let mut suggestions: Vec<Suggestion> = option_embeddings
+    options: &[Candidate],
+    semantic: Option<&mut SemanticSearch>,
+    args: &Args,
+    config: &SearchConfig,
+) -> Result<SearchResults, FuzzyError> {
+    if let Some(max_distance) = args.max_edit_distance {
+        let scorer = EditDistanceScorer::new(query, max_distance);
+        return Ok(get_suggestions(query, options, &scorer, config));
+    }
+    let fuzzy_scorer = FuzzyScorer {
+        config: &args.scoring_config,
+    };
+    // an empty query embeds to a meaningless vector, get_suggestions handles it without scoring
+    let Some(semantic) = semantic.filter(|_| !query.is_empty()) else {
+        return Ok(get_suggestions(query, options, &fuzzy_scorer, config));
+    };
+
+    let query_embedding = embedder::embed_query(semantic.model.as_mut(), query)?;
+    check_dimension(semantic.dimension, query_embedding.len())?;
+
+    // with --semantic-rerank only the best lexical matches are ranked semantically
+    let (options, lexical_timed_out) = match args.semantic_rerank {
+        Some(survivors) => {
+            let (best, timed_out) =
+                best_candidates(query, options, &fuzzy_scorer, config, survivors);
+            let best: Vec<Candidate> = best.into_iter().cloned().collect();
+            embed_missing(semantic, &best, args.batch_size)?;
+            (Cow::Owned(best), timed_out)
+        }
+        None => (Cow::Borrowed(options), false),
+    };
+
+    let semantic_scorer = SemanticScorer {
+        query_embedding: &query_embedding,
+        option_embeddings: &semantic.embeddings,
+        normalized: semantic.normalized,
+        config: &args.scoring_config,
+        threshold: args.semantic_threshold,
+    };
+    let scorer = CompositeScorer::new(vec![
+        (Box::new(fuzzy_scorer), 1.0 - args.semantic_weight),
+        (Box::new(semantic_scorer), args.semantic_weight),
+    ]);
+    let mut results = get_suggestions(query, &options, &scorer, config);
+    results.timed_out |= lexical_timed_out;
+    Ok(results)
+}
+
+// Reads the rows of the options that have no embedding yet from the embeddings file, embeds the
+// ones it doesn't have and keeps both for the following searches.
+fn embed_missing(
+    semantic: &mut SemanticSearch,
+    options: &[Candidate],
+    batch_size: usize,
+) -> Result<(), FuzzyError> {
+    let mut missing = missing_embeddings(options, &semantic.embeddings);
+    if let Some(index) = semantic.index.as_ref().filter(|_| !missing.is_empty()) {
+        let (texts, mut embeddings): (Vec<String>, Vec<Vec<f32>>) =
+            index.read_rows(&missing)?.into_iter().unzip();
+        // unit length like the embedded options below, so `normalized` keeps holding
+        algorithms::normalize_embeddings(&mut embeddings);
+        semantic
+            .embeddings
+            .extend(texts.into_iter().zip(embeddings));
+        missing.retain(|text| !semantic.embeddings.contains_key(*text));
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let mut embeddings = semantic.model.embed(missing.clone(), batch_size)?;
+    // unit length like the query embedding, so `normalized` still holds if it did for the file
+    algorithms::normalize_embeddings(&mut embeddings);
+    for (text, embedding) in missing.into_iter().zip(embeddings) {
+        semantic.embeddings.insert(text.to_string(), embedding);
+    }
+    Ok(())
+}
+
+// the --regex filter, an invalid pattern is an error naming it
+fn compile_regex(pattern: &str) -> Result<Regex, FuzzyError> {
+    Regex::new(pattern).map_err(|err| FuzzyError::Pattern {
+        pattern: pattern.to_string(),
+        message: err.to_string(),
+    })
+}
+
+// the --glob filter, reported like an invalid --regex
+fn compile_glob(pattern: &str) -> Result<GlobMatcher, FuzzyError> {
+    Glob::new(pattern)
+        .map(|glob| glob.compile_matcher())
+        .map_err(|err| FuzzyError::Pattern {
+            pattern: pattern.to_string(),
+            message: err.kind().to_string(),
+        })
+}
+
+// the rank Alt and a digit select, Alt+1 to Alt+9 that rank and Alt+0 the tenth
+fn digit_rank(c: char) -> Option<usize> {
+    let digit = c.to_digit(10)?;
+    Some(if digit == 0 { 10 } else { digit as usize })
+}
+
+// the stored vector of a word, for --inspect and --neighbors
+fn stored_embedding<'a>(
+    embeddings: &'a [(String, Vec<f32>)],
+    word: &str,
+    path: &str,
+) -> Result<&'a [f32], FuzzyError> {
+    embeddings
         .iter()
-        .filter_map(|(opt, emb)| algorithms::semantic_match(query, opt, query_embedding, emb))
-        .collect();
+        .find(|(key, _)| key == word)
+        .map(|(_, vector)| vector.as_slice())
+        .ok_or_else(|| FuzzyError::MissingEmbedding {
+            word: word.to_string(),
+            path: path.to_string(),
+        })
+}
 
-    suggestions.sort_by(|a, b| b.score.cmp(&a.score));
-    suggestions
+// the texts of the options that have no embedding (e.g. words.txt changed after generating)
+fn missing_embeddings<'a>(
+    options: &'a [Candidate],
+    option_embeddings: &EmbeddingMap,
+) -> Vec<&'a str> {
+    options
+        .iter()
+        .map(|opt| opt.text.as_str())
+        .filter(|text| !option_embeddings.contains_key(*text))
+        .collect()
 }
 
-fn main() -> io::Result<()> {
-    let options_file_path = "words.txt";
+// The args, with the config file as their defaults, or the code to exit with. --version is
+// answered first, before anything is loaded, so a config file that can't be read doesn't stop it.
+fn startup_args(
+    raw: Vec<String>,
+    load_config: impl FnOnce() -> Result<config::Config, FuzzyError>,
+) -> Result<Args, ExitCode> {
+    if cli::parse_args(raw.iter().cloned(), Args::default()).is_ok_and(|args| args.version) {
+        println!(
+            "fuzzyQ {} (embedding model {:?})",
+            env!("CARGO_PKG_VERSION"),
+            embedder::DEFAULT_MODEL
+        );
+        return Err(ExitCode::SUCCESS);
+    }
+    let defaults = match load_config() {
+        Ok(config) => config.into_args(),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return Err(ExitCode::from(2));
+        }
+    };
+    cli::parse_args(raw.into_iter(), defaults).map_err(|err| {
+        eprintln!("Error: {}", err);
+        ExitCode::from(2)
+    })
+}
+
+fn main() -> ExitCode {
+    let args = match startup_args(std::env::args().skip(1).collect(), config::load_config) {
+        Ok(args) => args,
+        Err(code) => return code,
+    };
+
+    if args.profile && !profile::ENABLED {
+        eprintln!(
+            "Warning: --profile needs a build with `--features profile`, no timings are collected"
+        );
+    }
+    let print_profile = args.profile && profile::ENABLED;
+
+    let code = match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::FAILURE
+        }
+    };
+    // after run returned, so the terminal is out of raw mode again
+    if print_profile {
+        eprint!("{}", profile::report());
+    }
+    code
+}
+
+fn run(args: Args) -> Result<(), FuzzyError> {
+    let options_file_path = args.words.as_deref().unwrap_or("words.txt");
     let embeddings_file_path = "word_embeddings.txt";
 
-    let sample_options = file_manager::read_file(options_file_path);
+    // invalid patterns are reported before anything is loaded
+    let regex = args.regex.as_deref().map(compile_regex).transpose()?;
+    let glob = args.glob.as_deref().map(compile_glob).transpose()?;
 
-    let pattern = std::env::args().nth(1).unwrap_or_default();
+    if let Some((query, candidate)) = &args.explain {
+        let breakdown = algorithms::explain(query, candidate, &args.scoring_config);
+        return output::print_breakdown(&breakdown, args.json);
+    }
 
-    if pattern == "--generate-embeddings" {
-        let option_embeddings = embedder::generate_embeddings_file(&sample_options);
-        file_manager::write_embeddings(&sample_options, option_embeddings, embeddings_file_path);
-        return Ok(());
+    // works on the embeddings file alone, the options aren't needed
+    if let Some(word) = &args.inspect {
+        let (embeddings, _) = file_manager::read_embeddings_file(embeddings_file_path)?;
+        let vector = stored_embedding(&embeddings, word, embeddings_file_path)?;
+        let limit = args.limit.unwrap_or(cli::DEFAULT_LIMIT);
+        let neighbors = algorithms::nearest_neighbors(word, vector, &embeddings, limit);
+        return output::print_embedding(vector, &neighbors);
     }
 
-    let semantic_search = pattern == "--semantic";
+    if let Some(word) = &args.neighbors {
+        let (embeddings, dimension) = file_manager::read_embeddings_file(embeddings_file_path)?;
+        // words that aren't in the file are embedded on the spot
+        let vector = match stored_embedding(&embeddings, word, embeddings_file_path) {
+            Ok(vector) => vector.to_vec(),
+            Err(_) => {
+                if !args.quiet {
+                    eprintln!(
+                        "Note: '{}' is not in {}, embedding it with the model",
+                        word, embeddings_file_path
+                    );
+                }
+                let mut model = embedder::get_model(&args.model_settings, true)?;
+                let vector = embedder::embed_query(&mut model, word)?;
+                check_dimension(dimension, vector.len())?;
+                vector
+            }
+        };
+        let neighbors = algorithms::nearest_neighbors(word, &vector, &embeddings, args.k);
+        return output::print_neighbors(&neighbors);
+    }
+
+    // stdin is read while the interface runs, the other modes need all the options up front
+    let streams = options_file_path == "-" && args.query.is_none() && !args.generate_embeddings;
+    let mut option_stream = None;
+    let mut sample_options = match &args.dir {
+        Some(dir) => file_manager::read_dir(dir, !args.no_ignore)?,
+        None if streams => {
+            option_stream = Some(file_manager::stream_file(
+                options_file_path,
+                args.input_format,
+            )?);
+            Vec::new()
+        }
+        None => file_manager::read_file(options_file_path, &args.input_format)?,
+    };
+    for opt in &mut sample_options {
+        opt.prepare_forms(&args.scoring_config);
+    }
+
+    if args.generate_embeddings {
+        let labels: Vec<String> = sample_options.iter().map(|opt| opt.text.clone()).collect();
+        return embedder::generate_embeddings_file(
+            &labels,
+            embeddings_file_path,
+            args.batch_size,
+            &args.model_settings,
+            args.embed_order,
+        );
+    }
 
-    let mut typed = String::new();
-    let mut last_suggestion_count = 0;
+    // shown in the header, e.g. when semantic search had to be disabled
+    let mut notice: Option<String> = None;
+    // shown instead of the notice until the query changes, e.g. after exporting results
+    let mut status: Option<String> = None;
+
+    let mut semantic: Option<SemanticSearch> = None;
+
+    if args.semantic {
+        let stored = read_option_embeddings(embeddings_file_path, args.semantic_rerank.is_some())?;
+        if let Some(stale) = stale_embeddings(
+            &sample_options,
+            &stored.embeddings,
+            embeddings_file_path,
+            &args,
+        ) {
+            eprintln!(
+                "Warning: {}. Rerun with --generate-embeddings to update it.",
+                stale
+            );
+        }
+        (semantic, notice) = start_semantic(stored, &args, || {
+            Ok(Box::new(embedder::get_model(&args.model_settings, true)?))
+        });
+    }
+
+    let mut search_config = SearchConfig {
+        limit: args.limit.unwrap_or(cli::DEFAULT_LIMIT),
+        browse: args.browse,
+        match_suffix: args.match_suffix,
+        regex,
+        glob,
+        min_len: args.min_len,
+        max_len: args.max_len,
+        max_scan: args.max_scan,
+        frecency: None,
+        sort: args.sort,
+        reverse: args.reverse,
+        tiers: args.tiers,
+        pinned: args.pins.iter().cloned().collect(),
+        cutoff_gap: args.cutoff_gap,
+        timeout: args.query_timeout_ms.map(Duration::from_millis),
+    };
+
+    let frecency_path = if args.frecency {
+        let path = config::frecency_path();
+        if path.is_none() {
+            eprintln!("Warning: neither XDG_DATA_HOME nor HOME is set, --frecency is ignored");
+        }
+        path
+    } else {
+        None
+    };
+    if let Some(path) = &frecency_path {
+        search_config.frecency = Some(file_manager::read_frecency(path)?);
+    }
+
+    // a single search without the interactive interface
+    if let Some(query) = &args.query {
+        let results = search(
+            query,
+            &sample_options,
+            semantic.as_mut(),
+            &args,
+            &search_config,
+        )?;
+        if results.timed_out {
+            eprintln!("Warning: the query timed out, the results only cover part of the options");
+        }
+        let format = if args.json {
+            ResultFormat::Json
+        } else if args.print0 {
+            ResultFormat::Nul
+        } else if args.plain {
+            ResultFormat::Plain
+        } else {
+            ResultFormat::Lines
+        };
+        return output::print_results(&results.suggestions, format);
+    }
+
+    let mut query = QueryInput::default();
+    let mut drawn = DrawnRows::default();
     let mut stdout = io::stdout();
 
-    let _guard = TerminalGuard::new()?;
+    let mut timing_log = match &args.timing_log {
+        Some(path) => Some((path.as_str(), file_manager::open_timing_log(path)?)),
+        None => None,
+    };
 
-    let mut embeddings: Option<Vec<(String, Vec<f32>)>> = None;
-    let mut model: Option<TextEmbedding> = None;
+    let draw_config = DrawConfig {
+        show_bar: !args.no_bar,
+        mark_semantic: args.mark_semantic,
+        numbered: args.numbered,
+        bar_scale: args.bar_scale,
+        truncate_left: args.truncate_left,
+    };
 
-    if semantic_search {
-        embeddings = Some(file_manager::read_embeddings_file(embeddings_file_path)?);
-        model = Some(embedder::get_model());
+    let guard = TerminalGuard::new()?;
+
+    // the separator and the lines of the preview pane
+    let preview_rows = args.preview.as_ref().map_or(0, |_| args.preview_lines + 1);
+    // without --limit the suggestions fill the terminal, and follow it when it's resized
+    if args.limit.is_none()
+        && let Ok((_, rows)) = terminal::size()
+    {
+        search_config.limit = draw::fitting_suggestions(rows, preview_rows);
     }
+    // the output for the suggestion it was run for, only rerun when the selection changes
+    let mut preview: Option<(String, Vec<String>)> = None;
+    let mut preview_scroll: usize = 0;
 
-    draw::draw_header(&mut stdout, &typed, 0 as f64)?;
-    draw::clear_previous_suggestions(&mut stdout, last_suggestion_count)?;
+    let mut mode = if semantic.is_some() {
+        SearchMode::Semantic
+    } else {
+        SearchMode::Lexical
+    };
+    let mut suggestions: Vec<Suggestion> = Vec::new();
+    let mut selected: usize = 0;
+    // the suggestion Enter was pressed on
+    let mut chosen: Option<String> = None;
+    let mut delta_time = 0.0;
+    let mut needs_search = true;
+    let mut needs_redraw = true;
+    // a search for newly read options keeps the selected suggestion selected
+    let mut keep_selection = false;
+    let mut last_stream_check = Instant::now();
+    let mut stream_error: Option<FuzzyError> = None;
 
     loop {
-        if event::poll(std::time::Duration::from_millis(10))? {
-            if let Event::Key(key_event) = event::read()? {
-                if key_event.modifiers.contains(KeyModifiers::CONTROL)
-                    && key_event.code == KeyCode::Char('c')
-                {
-                    break;
-                }
+        // newly read options are searched at most every STREAM_REFRESH, not for every line
+        if let Some(stream) = &option_stream
+            && last_stream_check.elapsed() >= STREAM_REFRESH
+        {
+            last_stream_check = Instant::now();
+            let known = sample_options.len();
+            let finished = file_manager::receive_options(stream, &mut sample_options)
+                .unwrap_or_else(|err| {
+                    stream_error = Some(err);
+                    true
+                });
+            for opt in &mut sample_options[known..] {
+                opt.prepare_forms(&args.scoring_config);
+            }
+            if sample_options.len() > known {
+                keep_selection = !needs_search;
+                needs_search = true;
+            }
+            if finished {
+                option_stream = None;
+            }
+        }
 
-                match key_event.code {
-                    KeyCode::Enter | KeyCode::Esc => break,
-                    KeyCode::Backspace => {
-                        typed.pop();
-                    }
-                    KeyCode::Char(c) => typed.push(c),
-                    _ => {}
-                }
+        if needs_search {
+            needs_search = false;
+            needs_redraw = true;
+            let start_time = Instant::now();
 
-                let start_time = Instant::now();
+            let results = search(
+                query.text(),
+                &sample_options,
+                semantic.as_mut().filter(|_| mode == SearchMode::Semantic),
+                &args,
+                &search_config,
+            )?;
+            let previous = suggestions
+                .get(selected)
+                .map(|sug| sug.text.clone())
+                .filter(|_| keep_selection);
+            keep_selection = false;
+            suggestions = results.suggestions;
+            selected = previous
+                .and_then(|text| suggestions.iter().position(|sug| sug.text == text))
+                .unwrap_or(0);
+            status = results
+                .timed_out
+                .then(|| "timed out, partial results".to_string());
+            delta_time = start_time.elapsed().as_secs_f64();
+            // buffered, so logging doesn't add a write to every keystroke
+            if let Some((path, log)) = &mut timing_log {
+                file_manager::write_timing(log, path, delta_time, query.text(), suggestions.len())?;
+            }
+        }
 
-                let mut suggestions = get_fuzzy_suggestions(&typed, &sample_options);
+        if needs_redraw {
+            needs_redraw = false;
+            selected = selected.min(suggestions.len().saturating_sub(1));
+            draw::clear_previous_suggestions(&mut stdout, &mut drawn)?;
+            draw::draw_suggestions(
+                &mut stdout,
+                &suggestions,
+                Some(selected),
+                &draw_config,
+                &mut drawn,
+            )?;
+            if suggestions.is_empty()
+                && !query.text().is_empty()
+                && let Some(hint) = args.hint.as_deref().filter(|hint| !hint.is_empty())
+            {
+                draw::draw_hint(&mut stdout, hint, &mut drawn)?;
+            }
+            if let Some(command) = &args.preview
+                && let Some(sug) = suggestions.get(selected)
+            {
+                if preview.as_ref().is_none_or(|(text, _)| *text != sug.text) {
+                    preview = Some((sug.text.clone(), preview::run_preview(command, &sug.text)));
+                    preview_scroll = 0;
+                }
+                if let Some((_, lines)) = &preview {
+                    preview_scroll = preview_scroll.min(lines.len().saturating_sub(1));
+                    draw::draw_preview(
+                        &mut stdout,
+                        &lines[preview_scroll..],
+                        args.preview_lines,
+                        &mut drawn,
+                    )?;
+                }
+            }
+            let unmatched_indices = match suggestions.first() {
+                Some(top) if args.show_unmatched => top.unmatched_query_indices.as_slice(),
+                _ => &[],
+            };
+            let header_notice = status.as_deref().or(notice.as_deref());
+            draw::draw_header(
+                &mut stdout,
+                &query,
+                unmatched_indices,
+                header_notice,
+                mode.label(),
+                delta_time,
+                args.query_style,
+            )?;
+            stdout.flush()?;
+        }
 
-                if semantic_search {
-                    let typed_embed = model.as_mut().unwrap().embed(&[&typed], None).unwrap();
-                    suggestions = get_semantic_suggestions(
-                        &typed,
-                        embeddings.as_ref().unwrap(),
-                        &typed_embed[0],
+        // poll returns as soon as a key arrives, the interval only bounds how long the loop sleeps
+        // before it can pick up other work; with 0 the loop blocks until the next event
+        // while options are streaming in, the loop also has to wake up for them
+        let interval = match args.poll_ms {
+            0 if option_stream.is_some() => Some(STREAM_REFRESH),
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        };
+        let ready = match interval {
+            Some(interval) => event::poll(interval)?,
+            None => true,
+        };
+        if !ready {
+            continue;
+        }
+        let key_event = match event::read()? {
+            Event::Key(key_event) => key_event,
+            // with bracketed paste a multi-line paste arrives in one piece instead of as Enter keys
+            Event::Paste(text) => {
+                needs_search = query.insert_str(&text);
+                continue;
+            }
+            Event::Resize(_, rows) => {
+                if args.limit.is_none() {
+                    search_config.limit = draw::fitting_suggestions(rows, preview_rows);
+                    needs_search = true;
+                }
+                needs_redraw = true;
+                continue;
+            }
+            _ => continue,
+        };
+        // digits alone go to the query
+        if args.numbered
+            && key_event.modifiers.contains(KeyModifiers::ALT)
+            && let KeyCode::Char(c) = key_event.code
+            && let Some(rank) = digit_rank(c)
+        {
+            if rank <= suggestions.len() {
+                selected = rank - 1;
+                needs_redraw = true;
+            }
+            continue;
+        }
+        if key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            match key_event.code {
+                KeyCode::Char('c') => break,
+                KeyCode::Char('w') => needs_search = query.delete_word(),
+                KeyCode::Char('u') => needs_search = query.clear(),
+                KeyCode::Char('t') => {
+                    let loaded_before = semantic.is_some();
+                    // loading can take a while, a first download even longer; its progress would
+                    // be printed over the interface
+                    let load_model = || -> ModelResult {
+                        draw::draw_header(
+                            &mut stdout,
+                            &query,
+                            &[],
+                            Some("loading the embedding model..."),
+                            mode.label(),
+                            delta_time,
+                            args.query_style,
+                        )?;
+                        stdout.flush()?;
+                        Ok(Box::new(embedder::get_model(&args.model_settings, false)?))
+                    };
+                    let toggled = toggle_mode(
+                        mode,
+                        &mut semantic,
+                        &sample_options,
+                        embeddings_file_path,
+                        &args,
+                        load_model,
                     );
+                    // the notice that semantic search was unavailable no longer holds
+                    if semantic.is_some() && !loaded_before {
+                        notice = toggled.notice;
+                    }
+                    mode = toggled.mode;
+                    status = toggled.status;
+                    needs_search |= toggled.search;
+                    needs_redraw = true;
                 }
+                KeyCode::Char('s') => {
+                    // a failed export is reported in the header rather than ending the session
+                    status = Some(
+                        match file_manager::write_results(&args.export_path, &suggestions) {
+                            Ok(()) => format!(
+                                "exported {} results to {}",
+                                suggestions.len(),
+                                args.export_path
+                            ),
+                            Err(err) => format!("export failed: {}", err),
+                        },
+                    );
+                    needs_redraw = true;
+                }
+                _ => {}
+            }
+            continue;
+        }
 
-                let top_suggestions = &suggestions[..suggestions.len().min(20)];
-                draw::clear_previous_suggestions(&mut stdout, last_suggestion_count)?;
-                draw::draw_suggestions(&mut stdout, top_suggestions)?;
-                draw::draw_header(&mut stdout, &typed, start_time.elapsed().as_secs_f64())?;
-                stdout.flush()?;
-
-                last_suggestion_count = top_suggestions.len();
+        match key_event.code {
+            KeyCode::Enter => {
+                chosen = suggestions.get(selected).map(|sug| sug.text.clone());
+                if let (Some(path), Some(frecency), Some(text)) =
+                    (&frecency_path, &mut search_config.frecency, &chosen)
+                {
+                    let now = structs::unix_now();
+                    frecency.record(text, now);
+                    frecency.prune(now);
+                    file_manager::write_frecency(path, frecency)?;
+                }
+                break;
+            }
+            KeyCode::Esc => break,
+            KeyCode::Backspace => needs_search = query.backspace(),
+            KeyCode::Delete => needs_search = query.delete(),
+            KeyCode::Char(c) => needs_search = query.insert(c),
+            KeyCode::Left => {
+                query.move_left();
+                needs_redraw = true;
+            }
+            KeyCode::Right => {
+                query.move_right();
+                needs_redraw = true;
+            }
+            KeyCode::Home => {
+                query.move_home();
+                needs_redraw = true;
+            }
+            KeyCode::End => {
+                query.move_end();
+                needs_redraw = true;
+            }
+            KeyCode::Up => {
+                selected = selected.saturating_sub(1);
+                needs_redraw = true;
+            }
+            KeyCode::Down => {
+                selected += 1;
+                needs_redraw = true;
+            }
+            KeyCode::PageUp if args.preview.is_some() => {
+                preview_scroll = preview_scroll.saturating_sub(args.preview_lines);
+                needs_redraw = true;
             }
+            KeyCode::PageDown if args.preview.is_some() => {
+                preview_scroll += args.preview_lines;
+                needs_redraw = true;
+            }
+            _ => {}
         }
     }
+    if let Some((path, log)) = &mut timing_log {
+        log.flush().map_err(|err| FuzzyError::io(path, err))?;
+    }
+    // out of raw mode first, opening a FIFO waits until the other end reads it
+    drop(guard);
+    if let Some(err) = stream_error {
+        eprintln!("Warning: {}, only the options before it were searched", err);
+    }
+    if let Some(path) = &args.output {
+        let terminator = if args.print0 { '\0' } else { '\n' };
+        file_manager::write_selection(path, chosen.as_deref(), terminator)?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn options(texts: &[&str]) -> Vec<Candidate> {
+        texts
+            .iter()
+            .map(|text| Candidate::new(text.to_string(), None, Vec::new()))
+            .collect()
+    }
+
+    #[test]
+    fn version_is_answered_despite_a_broken_config() {
+        let broken = || {
+            Err(FuzzyError::Config {
+                path: "config.toml".to_string(),
+                message: "expected `=`".to_string(),
+            })
+        };
+        let raw = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+        assert_eq!(
+            startup_args(raw(&["--version"]), broken).err(),
+            Some(ExitCode::SUCCESS)
+        );
+        // without it the config error ends the run, a "--version" value included
+        assert_eq!(
+            startup_args(raw(&["--query", "--version"]), broken).err(),
+            Some(ExitCode::from(2))
+        );
+        let args = startup_args(raw(&["--query", "--version"]), || Ok(Default::default()));
+        assert_eq!(
+            args.ok().and_then(|args| args.query).as_deref(),
+            Some("--version")
+        );
+    }
+
+    #[test]
+    fn options_missing_from_the_embeddings_are_counted() {
+        let options = options(&["apple", "banana", "cherry"]);
+        let embeddings = EmbeddingMap::from([("banana".to_string(), vec![1.0])]);
+        assert_eq!(
+            missing_embeddings(&options, &embeddings),
+            ["apple", "cherry"]
+        );
+        assert!(missing_embeddings(&options[1..2], &embeddings).is_empty());
+    }
+
+    // every query embeds to the same vector
+    struct FakeModel;
+
+    impl Embedder for FakeModel {
+        fn embed(
+            &mut self,
+            documents: Vec<&str>,
+            _batch_size: usize,
+        ) -> Result<Vec<Vec<f32>>, FuzzyError> {
+            Ok(documents.iter().map(|_| vec![1.0, 0.0]).collect())
+        }
+    }
+
+    fn fake_model() -> ModelResult {
+        Ok(Box::new(FakeModel))
+    }
+
+    fn no_model() -> ModelResult {
+        Err(FuzzyError::Model("no network".to_string()))
+    }
+
+    fn texts(results: &SearchResults) -> Vec<String> {
+        results
+            .suggestions
+            .iter()
+            .map(|sug| sug.text.clone())
+            .collect()
+    }
+
+    // an embeddings file with an embedding for application, and for apple pointing elsewhere
+    fn embeddings_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("fuzzyq-{}-{}", std::process::id(), name));
+        std::fs::write(&path, "application\t1 0\napple\t0 1\n").unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn semantic_search_without_the_model_falls_back_to_lexical() {
+        let options = options(&["apple", "application", "banana"]);
+        let args = Args {
+            semantic: true,
+            ..Args::default()
+        };
+        let stored = StoredEmbeddings {
+            embeddings: EmbeddingMap::from([("apple".to_string(), vec![1.0])]),
+            index: None,
+            dimension: Some(1),
+        };
+        let (mut semantic, notice) = start_semantic(stored, &args, no_model);
+        assert!(semantic.is_none());
+        assert_eq!(
+            notice.as_deref(),
+            Some("semantic search unavailable, using lexical")
+        );
+        let config = SearchConfig::default();
+        let results = search("app", &options, semantic.as_mut(), &args, &config).unwrap();
+        let scorer = FuzzyScorer {
+            config: &args.scoring_config,
+        };
+        let lexical = get_suggestions("app", &options, &scorer, &config);
+        assert_eq!(texts(&results), texts(&lexical));
+        assert_eq!(texts(&results), ["apple", "application"]);
+
+        let stored = StoredEmbeddings {
+            embeddings: EmbeddingMap::new(),
+            index: None,
+            dimension: None,
+        };
+        let (semantic, notice) = start_semantic(stored, &args, fake_model);
+        assert!(semantic.is_some());
+        assert!(notice.is_none());
+    }
+
+    #[test]
+    fn failing_to_load_the_model_keeps_lexical_mode() {
+        let path = embeddings_file("toggle-no-model.txt");
+        let mut semantic = None;
+        let options = options(&["apple", "application"]);
+        let toggled = toggle_mode(
+            SearchMode::Lexical,
+            &mut semantic,
+            &options,
+            &path,
+            &Args::default(),
+            no_model,
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(semantic.is_none());
+        assert_eq!(toggled.mode, SearchMode::Lexical);
+        assert_eq!(
+            toggled.status.as_deref(),
+            Some("semantic search unavailable: embedding model: no network")
+        );
+        assert!(!toggled.search);
+    }
+
+    #[test]
+    fn max_edit_distance_never_loads_the_model() {
+        let mut semantic = None;
+        let args = Args {
+            max_edit_distance: Some(2),
+            ..Args::default()
+        };
+        let toggled = toggle_mode(
+            SearchMode::Lexical,
+            &mut semantic,
+            &options(&["apple"]),
+            "missing.txt",
+            &args,
+            || panic!("the model was loaded"),
+        );
+        assert!(semantic.is_none());
+        assert_eq!(toggled.mode, SearchMode::Lexical);
+        assert!(toggled.status.is_some());
+        assert!(!toggled.search);
+    }
+
+    #[test]
+    fn first_switch_to_semantic_loads_once_and_reranks() {
+        let path = embeddings_file("toggle-semantic.txt");
+        let options = options(&["apple", "application", "banana"]);
+        let args = Args {
+            semantic_weight: 0.9,
+            ..Args::default()
+        };
+        let mut semantic = None;
+        let toggled = toggle_mode(
+            SearchMode::Lexical,
+            &mut semantic,
+            &options,
+            &path,
+            &args,
+            fake_model,
+        );
+        // the same check as --semantic, banana has no embedding
+        assert_eq!(
+            toggled.notice,
+            Some(format!(
+                "{} is stale, 1 options have no embedding and will be ranked lexically",
+                path
+            ))
+        );
+        assert_eq!(toggled.mode, SearchMode::Semantic);
+        assert!(toggled.status.is_none());
+        assert!(toggled.search);
+
+        let config = SearchConfig::default();
+        let lexical = search("app", &options, None, &args, &config).unwrap();
+        assert_eq!(texts(&lexical)[0], "apple");
+        let semantic_results = search("app", &options, semantic.as_mut(), &args, &config).unwrap();
+        assert_eq!(texts(&semantic_results)[0], "application");
+
+        // switching back and forth again reuses what was loaded
+        std::fs::remove_file(&path).unwrap();
+        let back = toggle_mode(toggled.mode, &mut semantic, &options, &path, &args, || {
+            panic!("the model was loaded twice")
+        });
+        assert_eq!(back.mode, SearchMode::Lexical);
+        let again = toggle_mode(back.mode, &mut semantic, &options, &path, &args, || {
+            panic!("the model was loaded twice")
+        });
+        assert_eq!(again.mode, SearchMode::Semantic);
+        assert!(again.notice.is_none());
+    }
+
+    #[test]
+    fn invalid_regex_is_a_pattern_error() {
+        assert!(compile_regex(r"\.rs$").unwrap().is_match("main.rs"));
+        let err = compile_regex("(unclosed").err().unwrap();
+        assert!(matches!(&err, FuzzyError::Pattern { pattern, .. } if pattern == "(unclosed"));
+    }
+
+    #[test]
+    fn invalid_glob_is_a_pattern_error() {
+        assert!(compile_glob("*.rs").unwrap().is_match("src/main.rs"));
+        let err = compile_glob("[unclosed").err().unwrap();
+        assert!(matches!(&err, FuzzyError::Pattern { pattern, .. } if pattern == "[unclosed"));
+    }
+
+    #[test]
+    fn inspected_words_are_looked_up_with_their_neighbors() {
+        let embeddings = vec![
+            ("cat".to_string(), vec![1.0, 0.0]),
+            ("dog".to_string(), vec![0.8, 0.6]),
+            ("car".to_string(), vec![0.0, 1.0]),
+        ];
+        let vector = stored_embedding(&embeddings, "cat", "embeddings.txt").unwrap();
+        assert_eq!(vector, [1.0, 0.0]);
+        // the word itself isn't its own neighbor
+        let neighbors = algorithms::nearest_neighbors("cat", vector, &embeddings, 5);
+        assert_eq!(neighbors, [("dog", 0.8), ("car", 0.0)]);
+        let missing = stored_embedding(&embeddings, "cow", "embeddings.txt").unwrap_err();
+        assert!(matches!(missing, FuzzyError::MissingEmbedding { .. }));
+        assert_eq!(
+            missing.to_string(),
+            "embeddings.txt: no embedding for 'cow'"
+        );
+    }
+
+    #[test]
+    fn digits_select_ranks_one_to_ten() {
+        assert_eq!(digit_rank('1'), Some(1));
+        assert_eq!(digit_rank('9'), Some(9));
+        assert_eq!(digit_rank('0'), Some(10));
+        assert_eq!(digit_rank('x'), None);
+    }
+
+    // like FakeModel, recording the documents of every call
+    struct RecordingModel(Rc<RefCell<Vec<Vec<String>>>>);
+
+    impl Embedder for RecordingModel {
+        fn embed(
+            &mut self,
+            documents: Vec<&str>,
+            batch_size: usize,
+        ) -> Result<Vec<Vec<f32>>, FuzzyError> {
+            let texts = documents.iter().map(|doc| doc.to_string()).collect();
+            self.0.borrow_mut().push(texts);
+            FakeModel.embed(documents, batch_size)
+        }
+    }
+
+    #[test]
+    fn only_the_lexical_survivors_are_embedded() {
+        let path = embeddings_file("rerank.txt");
+        let options = options(&["apple", "application", "banana", "apply", "cherry"]);
+        let args = Args {
+            semantic_rerank: Some(3),
+            ..Args::default()
+        };
+        let embedded = Rc::new(RefCell::new(Vec::new()));
+        let stored = read_option_embeddings(&path, true).unwrap();
+        // nothing is read from the file up front
+        assert!(stored.embeddings.is_empty());
+        let mut semantic = load_semantic(stored, || {
+            Ok(Box::new(RecordingModel(Rc::clone(&embedded))))
+        })
+        .unwrap();
+        let results = search(
+            "appl",
+            &options,
+            Some(&mut semantic),
+            &args,
+            &SearchConfig::default(),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // "application" and "apple" are in the file, only the query and "apply" go to the model
+        assert_eq!(*embedded.borrow(), [vec!["appl"], vec!["apply"]]);
+        let mut loaded: Vec<&str> = semantic.embeddings.keys().map(String::as_str).collect();
+        loaded.sort_unstable();
+        assert_eq!(loaded, ["apple", "application", "apply"]);
+        // the other options are never scored semantically
+        assert!(
+            texts(&results)
+                .iter()
+                .all(|text| loaded.contains(&text.as_str()))
+        );
+
+        // the next search reuses them
+        search(
+            "app",
+            &options,
+            Some(&mut semantic),
+            &args,
+            &SearchConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(embedded.borrow().len(), 3);
+    }
+
+    #[test]
+    fn query_embeddings_must_have_the_file_dimension() {
+        assert!(check_dimension(Some(3), 3).is_ok());
+        // an empty file has no dimension to check
+        assert!(check_dimension(None, 384).is_ok());
+        let mismatch = check_dimension(Some(3), 384).unwrap_err();
+        assert!(matches!(
+            mismatch,
+            FuzzyError::DimensionMismatch {
+                expected: 3,
+                found: 384
+            }
+        ));
+    }
+
+    #[test]
+    fn semantic_summary_names_the_model_dimension_and_count() {
+        assert_eq!(
+            semantic_summary("all-MiniLM-L6-v2", Some(384), 20_000),
+            "Semantic search: all-MiniLM-L6-v2, 384 dimensions, 20000 embeddings loaded"
+        );
+        assert_eq!(
+            semantic_summary("all-MiniLM-L6-v2", None, 0),
+            "Semantic search: all-MiniLM-L6-v2, no embeddings loaded"
+        );
+    }
+}
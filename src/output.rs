@@ -0,0 +1,252 @@
+use crate::structs::{FuzzyError, MatchTier, MatchedField, ScoreBreakdown, Suggestion};
+use serde::Serialize;
+use std::io::{self, Write};
+
+#[derive(Serialize)]
+struct JsonResult<'a> {
+    text: &'a str,
+    score: usize,
+    // character offsets into text, not byte offsets
+    match_indices: Vec<usize>,
+    matched_field: Option<MatchedField>,
+    // only with --tiers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tier: Option<MatchTier>,
+}
+
+#[derive(Serialize)]
+struct JsonBreakdown<'a> {
+    #[serde(flatten)]
+    passes: &'a ScoreBreakdown,
+    total: usize,
+}
+
+// match indices are byte offsets internally, consumers of the json want characters
+fn char_offsets(text: &str, byte_indices: &[usize]) -> Vec<usize> {
+    byte_indices
+        .iter()
+        .filter(|&&index| text.is_char_boundary(index) && index < text.len())
+        .map(|&index| text[..index].chars().count())
+        .collect()
+}
+
+// a failed write to stdout, e.g. a closed pipe, is an output error rather than a terminal one
+fn stdout_error(err: io::Error) -> FuzzyError {
+    FuzzyError::io("stdout", err)
+}
+
+// the vector on one line, then one `neighbor\tsimilarity` line per neighbor
+pub fn print_embedding(vector: &[f32], neighbors: &[(&str, f32)]) -> Result<(), FuzzyError> {
+    let values: Vec<String> = vector.iter().map(f32::to_string).collect();
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", values.join(","))
+        .and_then(|()| write_neighbors(&mut stdout, neighbors))
+        .map_err(stdout_error)
+}
+
+// one `neighbor\tsimilarity` line per neighbor
+pub fn print_neighbors(neighbors: &[(&str, f32)]) -> Result<(), FuzzyError> {
+    write_neighbors(&mut io::stdout().lock(), neighbors).map_err(stdout_error)
+}
+
+fn write_neighbors(stdout: &mut impl Write, neighbors: &[(&str, f32)]) -> io::Result<()> {
+    for (neighbor, similarity) in neighbors {
+        writeln!(stdout, "{}\t{:.4}", neighbor, similarity)?;
+    }
+    stdout.flush()
+}
+
+pub enum ResultFormat {
+    // `text\tscore` lines
+    Lines,
+    Json,
+    // only the texts, each ended by a NUL byte like `find -print0`, for `xargs -0`
+    Nul,
+    // only the texts, one per line and without the colors of --raw
+    Plain,
+}
+
+// one `pass\tpoints` line per pass, then the total, or with json an object with a field for each
+pub fn print_breakdown(breakdown: &ScoreBreakdown, json: bool) -> Result<(), FuzzyError> {
+    write_breakdown(&mut io::stdout().lock(), breakdown, json).map_err(stdout_error)
+}
+
+fn write_breakdown(
+    stdout: &mut impl Write,
+    breakdown: &ScoreBreakdown,
+    json: bool,
+) -> io::Result<()> {
+    if json {
+        let object = JsonBreakdown {
+            passes: breakdown,
+            total: breakdown.total(),
+        };
+        serde_json::to_writer(&mut *stdout, &object).map_err(io::Error::from)?;
+        writeln!(stdout)?;
+        return stdout.flush();
+    }
+    let passes = [
+        ("exact", breakdown.exact),
+        ("substring", breakdown.substring),
+        ("prefix", breakdown.prefix),
+        ("subsequence", breakdown.subsequence),
+        ("gaps", breakdown.gaps),
+        ("acronym", breakdown.acronym),
+        ("edit distance", breakdown.edit_distance),
+        ("total", breakdown.total()),
+    ];
+    for (pass, points) in passes {
+        writeln!(stdout, "{}\t{}", pass, points)?;
+    }
+    stdout.flush()
+}
+
+pub fn print_results(suggestions: &[Suggestion], format: ResultFormat) -> Result<(), FuzzyError> {
+    write_results(&mut io::stdout().lock(), suggestions, format).map_err(stdout_error)
+}
+
+fn write_results(
+    stdout: &mut impl Write,
+    suggestions: &[Suggestion],
+    format: ResultFormat,
+) -> io::Result<()> {
+    if let ResultFormat::Json = format {
+        let results: Vec<JsonResult> = suggestions
+            .iter()
+            .map(|sug| JsonResult {
+                text: &sug.text,
+                score: sug.score,
+                match_indices: char_offsets(&sug.text, &sug.match_indices),
+                matched_field: sug.matched_field,
+                tier: sug.tier,
+            })
+            .collect();
+        serde_json::to_writer(&mut *stdout, &results).map_err(io::Error::from)?;
+        writeln!(stdout)?;
+    } else {
+        // the colored line as read with --raw, so the output can be piped on like the input
+        for sug in suggestions {
+            let text = sug.display.as_deref().unwrap_or(&sug.text);
+            match format {
+                ResultFormat::Nul => write!(stdout, "{}\0", text)?,
+                ResultFormat::Plain => writeln!(stdout, "{}", sug.text)?,
+                _ => writeln!(stdout, "{}\t{}", text, sug.score)?,
+            }
+        }
+    }
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a reader that went away, like `head` closing the pipe
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn a_closed_pipe_is_an_output_error() {
+        let apple = Suggestion {
+            text: "apple".to_string(),
+            ..Default::default()
+        };
+        let err = write_results(&mut ClosedPipe, &[apple], ResultFormat::Plain)
+            .map_err(stdout_error)
+            .unwrap_err();
+        assert!(matches!(&err, FuzzyError::Io { path, .. } if path == "stdout"));
+        assert!(err.to_string().starts_with("stdout: "));
+    }
+
+    #[test]
+    fn json_match_indices_are_char_offsets() {
+        // "é" and "ü" take two bytes each, so the "r" of "über" is byte 6 but char 4
+        let text = "café über";
+        let bytes: Vec<usize> = ["é", "ü", "r"]
+            .iter()
+            .map(|c| text.rfind(c).unwrap())
+            .collect();
+        assert_eq!(bytes, [3, 6, 10]);
+        let offsets = char_offsets(text, &bytes);
+        assert_eq!(offsets, [3, 5, 8]);
+        let chars: Vec<char> = text.chars().collect();
+        let marked: String = offsets.iter().map(|&i| chars[i]).collect();
+        assert_eq!(marked, "éür");
+    }
+
+    #[test]
+    fn offsets_outside_the_text_or_inside_a_char_are_dropped() {
+        assert_eq!(char_offsets("é", &[0, 1, 2]), [0]);
+    }
+
+    #[test]
+    fn print0_separates_the_results_with_nul_bytes() {
+        let suggestions: Vec<Suggestion> = ["my file.txt", "two\nlines"]
+            .iter()
+            .map(|text| Suggestion {
+                text: text.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let mut buffer = Vec::new();
+        write_results(&mut buffer, &suggestions, ResultFormat::Nul).unwrap();
+        assert_eq!(buffer, b"my file.txt\0two\nlines\0");
+    }
+
+    #[test]
+    fn plain_output_is_only_the_texts_in_ranked_order() {
+        let suggestions = [("pear", 900), ("spear", 400)].map(|(text, score)| Suggestion {
+            text: text.to_string(),
+            score,
+            display: Some(format!("\x1b[32m{text}\x1b[0m")),
+            ..Default::default()
+        });
+        let mut buffer = Vec::new();
+        write_results(&mut buffer, &suggestions, ResultFormat::Plain).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), "pear\nspear\n");
+        // the default keeps the colors of --raw and adds the score
+        let mut buffer = Vec::new();
+        write_results(&mut buffer, &suggestions[..1], ResultFormat::Lines).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "\x1b[32mpear\x1b[0m\t900\n"
+        );
+    }
+
+    #[test]
+    fn json_breakdown_has_a_number_for_every_pass_and_the_total() {
+        let config = crate::structs::ScoringConfig::default();
+        let breakdown = crate::algorithms::explain("tdd", "Test Driven Development", &config);
+        let mut buffer = Vec::new();
+        write_breakdown(&mut buffer, &breakdown, true).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        let object = value.as_object().unwrap();
+        let mut fields: Vec<&str> = object.keys().map(String::as_str).collect();
+        fields.sort_unstable();
+        assert_eq!(
+            fields,
+            [
+                "acronym",
+                "edit_distance",
+                "exact",
+                "gaps",
+                "prefix",
+                "subsequence",
+                "substring",
+                "total"
+            ]
+        );
+        assert!(object.values().all(serde_json::Value::is_u64));
+        assert_eq!(object["acronym"], 250);
+        assert_eq!(object["total"], breakdown.total());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::file_manager::strip_ansi;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// a preview that takes longer is killed, so a hanging command can't freeze the interface
+const PREVIEW_TIMEOUT: Duration = Duration::from_secs(1);
+// output past this is dropped rather than held in memory, only the first lines are shown anyway
+const MAX_PREVIEW_BYTES: u64 = 64 * 1024;
+
+// single quoted for sh, a quote inside becomes '\''
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+// Every `{}` in the command is replaced by the quoted text, without one the text is appended as
+// the last argument.
+fn preview_command(command: &str, text: &str) -> String {
+    let quoted = shell_quote(text);
+    if command.contains("{}") {
+        command.replace("{}", &quoted)
+    } else {
+        format!("{} {}", command, quoted)
+    }
+}
+
+// Runs the command through sh with stderr folded into stdout, so errors are shown in the pane
+// like the output. Failures to run it and timeouts become a line of their own.
+pub fn run_preview(command: &str, text: &str) -> Vec<String> {
+    let script = format!("exec 2>&1\n{}", preview_command(command, text));
+    let mut child = match Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => return vec![format!("preview failed: {}", err)],
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return Vec::new();
+    };
+
+    // read on a thread so the wait can time out, a background process may keep the pipe open
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let result = stdout.take(MAX_PREVIEW_BYTES).read_to_end(&mut output);
+        let _ = sender.send(result.map(|_| output));
+    });
+    let output = match receiver.recv_timeout(PREVIEW_TIMEOUT) {
+        Ok(Ok(output)) => output,
+        Ok(Err(err)) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return vec![format!("preview failed: {}", err)];
+        }
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return vec![format!("preview timed out after {:?}", PREVIEW_TIMEOUT)];
+        }
+    };
+    // with more output than is read the command can still be writing, it's not needed anymore
+    let truncated = output.len() as u64 >= MAX_PREVIEW_BYTES;
+    if truncated {
+        let _ = child.kill();
+    }
+    let status = child.wait();
+
+    let mut lines: Vec<String> = String::from_utf8_lossy(&output)
+        .lines()
+        .map(|line| {
+            strip_ansi(line)
+                .replace('\t', "    ")
+                .chars()
+                .filter(|ch| !ch.is_control())
+                .collect()
+        })
+        .collect();
+    // a truncated command fails from the closed pipe, which isn't worth reporting
+    if let Ok(status) = status
+        && !truncated
+        && !status.success()
+        && status.code().is_some()
+    {
+        lines.push(format!("({})", status));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_quoted_into_the_command() {
+        assert_eq!(preview_command("cat {}", "it's"), r"cat 'it'\''s'");
+        assert_eq!(
+            preview_command("head -n 3", "my file"),
+            "head -n 3 'my file'"
+        );
+        assert_eq!(
+            run_preview("printf '%s\\n'", "$(echo hi); rm -rf x"),
+            ["$(echo hi); rm -rf x"]
+        );
+    }
+
+    #[test]
+    fn errors_and_long_output_stay_in_the_pane() {
+        let failed = run_preview("echo oops >&2; exit 3; true", "x");
+        assert_eq!(failed, ["oops", "(exit status: 3)"]);
+        // cut at MAX_PREVIEW_BYTES, without reporting the closed pipe
+        let long = run_preview("yes", "line");
+        assert_eq!(
+            long.len() as u64,
+            MAX_PREVIEW_BYTES / "line\n".len() as u64 + 1
+        );
+        // the last line can be cut short
+        assert!(long.iter().all(|line| "line".starts_with(line.as_str())));
+        let colored = run_preview("printf '\\033[31mred\\tx\\033[0m\\n'", "");
+        assert_eq!(colored, ["red    x"]);
+    }
+}
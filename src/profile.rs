@@ -0,0 +1,109 @@
+// Per pass timings for --profile. Without the `profile` feature the timers are empty and compile
+// away, so the scoring passes pay nothing for them by default.
+#[cfg(feature = "profile")]
+use std::cell::RefCell;
+#[cfg(feature = "profile")]
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy)]
+pub enum Pass {
+    Substring,
+    Subsequence,
+    Acronym,
+    EditDistance,
+    Cosine,
+}
+
+const PASS_NAMES: [&str; 5] = [
+    "substring",
+    "subsequence",
+    "acronym",
+    "edit distance",
+    "cosine",
+];
+
+#[cfg(feature = "profile")]
+thread_local! {
+    // total time and number of runs per pass
+    static TOTALS: RefCell<[(Duration, usize); 5]> = const { RefCell::new([(Duration::ZERO, 0); 5]) };
+}
+
+pub const ENABLED: bool = cfg!(feature = "profile");
+
+// adds the time until it's dropped to the pass
+pub struct Timer {
+    #[cfg(feature = "profile")]
+    pass: Pass,
+    #[cfg(feature = "profile")]
+    start: Instant,
+}
+
+pub fn timer(pass: Pass) -> Timer {
+    #[cfg(not(feature = "profile"))]
+    let _ = pass;
+    Timer {
+        #[cfg(feature = "profile")]
+        pass,
+        #[cfg(feature = "profile")]
+        start: Instant::now(),
+    }
+}
+
+#[cfg(feature = "profile")]
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        TOTALS.with(|totals| {
+            let total = &mut totals.borrow_mut()[self.pass as usize];
+            total.0 += elapsed;
+            total.1 += 1;
+        });
+    }
+}
+
+// one `pass\ttotal\truns` line per pass
+pub fn report() -> String {
+    #[cfg(feature = "profile")]
+    let totals = TOTALS.with(|totals| *totals.borrow());
+    #[cfg(not(feature = "profile"))]
+    let totals = [(std::time::Duration::ZERO, 0usize); 5];
+    PASS_NAMES
+        .iter()
+        .zip(totals)
+        .map(|(name, (time, runs))| {
+            format!(
+                "{}\t{:.3}ms\t{} runs\n",
+                name,
+                time.as_secs_f64() * 1000.0,
+                runs
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_lists_every_pass() {
+        let report = report();
+        let passes: Vec<&str> = report
+            .lines()
+            .map(|line| line.split('\t').next().unwrap())
+            .collect();
+        assert_eq!(passes, PASS_NAMES);
+    }
+
+    #[cfg(feature = "profile")]
+    #[test]
+    fn timers_count_their_runs() {
+        // the totals are per thread, so other tests don't add to them
+        let runs = |pass: Pass| TOTALS.with(|totals| totals.borrow()[pass as usize].1);
+        let before = runs(Pass::EditDistance);
+        drop(timer(Pass::EditDistance));
+        drop(timer(Pass::EditDistance));
+        assert_eq!(runs(Pass::EditDistance), before + 2);
+        assert!(report().contains("edit distance\t"));
+    }
+}
@@ -0,0 +1,1088 @@
+use crate::algorithms::{self, MAX_SCORE, RANK_SCALE, Scorer};
+use crate::structs::{
+    self, Candidate, MatchTier, MatchedField, PreparedCandidate, SearchConfig, SortOrder,
+    Suggestion,
+};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Instant;
+
+// with match_suffix only the text after the last ':' is scored (e.g. the code in `file:line: code`)
+fn suffix_offset(candidate: &Candidate, config: &SearchConfig) -> Option<usize> {
+    let pos = candidate.text.rfind(':')?;
+    config.match_suffix.then_some(pos + 1)
+}
+
+// the suffix scored with match_suffix, its offset into the label and its prepared features
+fn scored_suffix<'a>(
+    candidate: &'a Candidate,
+    config: &SearchConfig,
+) -> Option<(usize, &'a PreparedCandidate)> {
+    let offset = suffix_offset(candidate, config)?;
+    Some((offset, candidate.prepared_suffix.as_ref()?))
+}
+
+// the description and keywords with their prepared features and the divisor of their score
+fn scored_fields(
+    candidate: &Candidate,
+) -> impl Iterator<Item = (&String, &PreparedCandidate, usize, MatchedField)> {
+    let description = candidate
+        .description
+        .iter()
+        .zip(&candidate.prepared_description)
+        .map(|(desc, prepared)| (desc, prepared, 2, MatchedField::Description));
+    let keywords = candidate
+        .keywords
+        .iter()
+        .zip(&candidate.prepared_keywords)
+        .map(|(keyword, prepared)| (keyword, prepared, 1, MatchedField::Keyword));
+    description.chain(keywords)
+}
+
+// the highlights of a suffix match are shifted back so they line up with the full label
+fn score_label(
+    query: &str,
+    candidate: &Candidate,
+    scorer: &dyn Scorer,
+    config: &SearchConfig,
+) -> Option<Suggestion> {
+    let label = &candidate.text;
+    let Some((offset, prepared)) = scored_suffix(candidate, config) else {
+        return scorer.score_prepared(query, label, &candidate.prepared);
+    };
+    let mut sug = scorer.score_prepared(query, &label[offset..], prepared)?;
+    sug.text = label.clone();
+    for idx in sug.match_indices.iter_mut() {
+        *idx += offset;
+    }
+    Some(sug)
+}
+
+// Scores the label, the description and every hidden keyword and keeps the best. Only label
+// matches are highlighted and description matches count for half.
+fn score_candidate(
+    query: &str,
+    candidate: &Candidate,
+    scorer: &dyn Scorer,
+    config: &SearchConfig,
+) -> Option<Suggestion> {
+    let mut best = score_label(query, candidate, scorer, config).map(|sug| Suggestion {
+        matched_field: Some(MatchedField::Label),
+        ..sug
+    });
+    for (field, prepared, divisor, matched_field) in scored_fields(candidate) {
+        if let Some(sug) = scorer.score_prepared(query, field, prepared)
+            && best.as_ref().is_none_or(|b| sug.score / divisor > b.score)
+        {
+            best = Some(Suggestion {
+                text: candidate.text.clone(),
+                score: sug.score / divisor,
+                unmatched_query_indices: sug.unmatched_query_indices,
+                semantic_only: sug.semantic_only,
+                matched_field: Some(matched_field),
+                ..Default::default()
+            });
+        }
+    }
+    best.map(|sug| Suggestion {
+        description: candidate.description.clone(),
+        icon: candidate.icon.clone(),
+        display: candidate.display.clone(),
+        columns: candidate.columns.clone(),
+        ..sug
+    })
+}
+
+// the score score_candidate gives in 1/RANK_SCALE points, computed without building any highlights
+fn candidate_score(
+    query: &str,
+    candidate: &Candidate,
+    scorer: &dyn Scorer,
+    config: &SearchConfig,
+) -> Option<usize> {
+    let label_score = match scored_suffix(candidate, config) {
+        Some((offset, prepared)) => scorer.rank_score(query, &candidate.text[offset..], prepared),
+        None => scorer.rank_score(query, &candidate.text, &candidate.prepared),
+    };
+    scored_fields(candidate)
+        .filter_map(|(field, prepared, divisor, _)| {
+            let score = scorer.rank_score(query, field, prepared)?;
+            Some(score / divisor)
+        })
+        .chain(label_score)
+        .max()
+}
+
+// pre-filters that exclude an option before it is scored at all
+fn passes_filters(candidate: &Candidate, config: &SearchConfig) -> bool {
+    if config.min_len.is_some() || config.max_len.is_some() {
+        // chars rather than bytes so multibyte text isn't treated as longer than it looks
+        let len = candidate.text.chars().count();
+        if config.min_len.is_some_and(|min| len < min)
+            || config.max_len.is_some_and(|max| len > max)
+        {
+            return false;
+        }
+    }
+    config
+        .regex
+        .as_ref()
+        .is_none_or(|regex| regex.is_match(&candidate.text))
+        && config
+            .glob
+            .as_ref()
+            .is_none_or(|glob| glob.is_match(&candidate.text))
+}
+
+// The count best scoring options, best first and one per text. The same text can come from
+// several lines, e.g. when word files are merged, and score differently through their
+// descriptions or keywords, then its best scoring line counts. Only count options are held at
+// once: a better line of a kept text takes the place of the worse one, at the position the text
+// was first seen, and ties go to the text seen first.
+fn best_per_text<'a>(
+    scored: impl Iterator<Item = (usize, &'a Candidate)>,
+    count: usize,
+) -> Vec<&'a Candidate> {
+    // ordered worst first, the lowest score and of that the one seen last
+    let mut kept: BTreeMap<(usize, Reverse<usize>), &Candidate> = BTreeMap::new();
+    let mut keys: HashMap<&str, (usize, Reverse<usize>)> = HashMap::new();
+    for (seen, (score, opt)) in scored.enumerate() {
+        let key = match keys.get(opt.text.as_str()) {
+            Some(&(kept_score, kept_seen)) if score > kept_score => {
+                kept.remove(&(kept_score, kept_seen));
+                (score, kept_seen)
+            }
+            Some(_) => continue,
+            None => (score, Reverse(seen)),
+        };
+        kept.insert(key, opt);
+        keys.insert(opt.text.as_str(), key);
+        if kept.len() > count
+            && let Some((_, worst)) = kept.pop_first()
+        {
+            keys.remove(worst.text.as_str());
+        }
+    }
+    kept.into_values().rev().collect()
+}
+
+// ranked suggestions come in by descending score, browsed ones in file order
+fn sort_suggestions(mut suggestions: Vec<Suggestion>, config: &SearchConfig) -> Vec<Suggestion> {
+    if let SortOrder::Name = config.sort {
+        suggestions.sort_by(|a, b| a.text.cmp(&b.text));
+    }
+    if config.reverse {
+        suggestions.reverse();
+    }
+    // stable, so the pins and the rest each keep the order chosen above
+    suggestions.sort_by_key(|sug| !sug.pinned);
+    suggestions
+}
+
+// Drops everything from the first suggestion scoring less than (1 - gap) times the one before it.
+// The suggestions are still in rank order, so the break is found before they're sorted by name.
+// The pins come first and are always kept.
+fn cut_at_gap(suggestions: &mut Vec<Suggestion>, gap: f32) {
+    let pins = suggestions.iter().take_while(|sug| sug.pinned).count();
+    let cut = suggestions[pins..]
+        .windows(2)
+        .position(|pair| (pair[1].score as f32) < pair[0].score as f32 * (1.0 - gap));
+    if let Some(pos) = cut {
+        suggestions.truncate(pins + pos + 1);
+    }
+}
+
+pub struct SearchResults {
+    pub suggestions: Vec<Suggestion>,
+    // the timeout ran out before every option was scored, the suggestions are the best of the
+    // options scored until then
+    pub timed_out: bool,
+}
+
+pub fn get_suggestions(
+    query: &str,
+    options: &[Candidate],
+    scorer: &dyn Scorer,
+    config: &SearchConfig,
+) -> SearchResults {
+    let (mut suggestions, timed_out) = best_suggestions(query, options, scorer, config);
+    if let Some(gap) = config.cutoff_gap {
+        cut_at_gap(&mut suggestions, gap);
+    }
+    SearchResults {
+        suggestions: sort_suggestions(suggestions, config),
+        timed_out,
+    }
+}
+
+// how many options are scored between looks at the clock
+const TIMEOUT_CHECK_INTERVAL: usize = 256;
+
+fn best_suggestions(
+    query: &str,
+    options: &[Candidate],
+    scorer: &dyn Scorer,
+    config: &SearchConfig,
+) -> (Vec<Suggestion>, bool) {
+    // an empty query has nothing to rank on, so either list options in file order or nothing
+    if query.is_empty() {
+        if !config.browse {
+            return (Vec::new(), false);
+        }
+        let mut seen = HashSet::new();
+        let is_pinned = |opt: &&Candidate| config.pinned.contains(&opt.text);
+        // the pins first, so the limit can't leave them out
+        let browsed = options
+            .iter()
+            .filter(is_pinned)
+            .chain(options.iter().filter(|opt| !is_pinned(opt)))
+            .filter(|opt| passes_filters(opt, config))
+            .filter(|opt| seen.insert(opt.text.as_str()))
+            .take(config.limit)
+            .map(|opt| Suggestion {
+                text: opt.text.clone(),
+                description: opt.description.clone(),
+                icon: opt.icon.clone(),
+                display: opt.display.clone(),
+                columns: opt.columns.clone(),
+                pinned: is_pinned(&opt),
+                ..Default::default()
+            })
+            .collect();
+        return (browsed, false);
+    }
+
+    let (suggestions, timed_out) = ranked_suggestions(query, options, scorer, config);
+    (suggestions.collect(), timed_out)
+}
+
+// The ranked suggestions of get_suggestions for a non-empty query, best first, without the sorting
+// and the cutoff. Only the best config.limit options are kept while ranking, and each suggestion
+// with its highlights is built when it is taken, so taking a few of them doesn't build the rest.
+// Also tells whether the timeout cut the scan short.
+pub fn ranked_suggestions<'a>(
+    query: &'a str,
+    options: &'a [Candidate],
+    scorer: &'a dyn Scorer,
+    config: &'a SearchConfig,
+) -> (impl Iterator<Item = Suggestion> + 'a, bool) {
+    let boosted = frecency_boost(config);
+    let tier_query = config.tiers.then(|| tier_query(query));
+    let (best, timed_out) = best_candidates(query, options, scorer, config, config.limit);
+    let suggestions = best.into_iter().filter_map(move |opt| {
+        let sug = score_candidate(query, opt, scorer, config)?;
+        Some(Suggestion {
+            score: boosted(sug.score, opt),
+            tier: tier_query.as_deref().map(|q| match_tier(q, opt, config)),
+            pinned: config.pinned.contains(&opt.text),
+            ..sug
+        })
+    });
+    (suggestions, timed_out)
+}
+
+// the query as the label is compared to it for the tiers, like the prepared lowercase label
+fn tier_query(query: &str) -> String {
+    algorithms::lowercase_nfc(query)
+}
+
+// how the label, or its suffix with match_suffix, contains the query given by tier_query
+fn match_tier(query: &str, candidate: &Candidate, config: &SearchConfig) -> MatchTier {
+    let label = candidate.prepared.lowercase.as_str();
+    let label = match label.rfind(':') {
+        Some(pos) if config.match_suffix => &label[pos + 1..],
+        _ => label,
+    };
+    if label == query {
+        MatchTier::Exact
+    } else if label.starts_with(query) {
+        MatchTier::Prefix
+    } else if label.contains(query) {
+        MatchTier::Substring
+    } else {
+        MatchTier::Fuzzy
+    }
+}
+
+// a step above the best tiered score, so a matching pin outranks every other match
+const PINNED_STEP: usize = (MatchTier::Fuzzy as usize + 1) * (MAX_SCORE + 1);
+
+// scores are at most MAX_SCORE, so one step per tier keeps each tier above all of the next
+fn tiered_score(score: usize, tier: MatchTier) -> usize {
+    let steps = MatchTier::Fuzzy as usize - tier as usize;
+    steps * (MAX_SCORE + 1) + score.min(MAX_SCORE)
+}
+
+// frecency only reorders matches, it never makes an option match; the clock is only read when
+// there is a store, wasm32-unknown-unknown has none
+fn frecency_boost(config: &SearchConfig) -> impl Fn(usize, &Candidate) -> usize + '_ {
+    let now = config.frecency.as_ref().map(|_| structs::unix_now());
+    move |score, opt| {
+        // a score of 0 is no match
+        if score == 0 {
+            return 0;
+        }
+        let bonus = match (&config.frecency, now) {
+            (Some(frecency), Some(now)) => frecency.bonus(&opt.text, now),
+            _ => 0,
+        };
+        (score + bonus).min(MAX_SCORE)
+    }
+}
+
+// The count best scoring options of a non-empty query, without building their suggestions, so
+// they can also be ranked again by a costlier scorer. Also tells whether the timeout cut the
+// scan short.
+pub fn best_candidates<'a>(
+    query: &str,
+    options: &'a [Candidate],
+    scorer: &dyn Scorer,
+    config: &SearchConfig,
+    count: usize,
+) -> (Vec<&'a Candidate>, bool) {
+    let boosted = frecency_boost(config);
+    let tier_query = config.tiers.then(|| tier_query(query));
+
+    // like the frecency clock, only read with a timeout
+    let deadline = config.timeout.map(|timeout| Instant::now() + timeout);
+    let mut timed_out = false;
+
+    // rank on the bare scores and only build the suggestions that are shown
+    let scanned = config.max_scan.unwrap_or(options.len());
+    let scored = options[..scanned.min(options.len())]
+        .iter()
+        .enumerate()
+        .take_while(|&(i, _)| {
+            if i % TIMEOUT_CHECK_INTERVAL == 0
+                && i > 0
+                && deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                timed_out = true;
+            }
+            !timed_out
+        })
+        .map(|(_, opt)| opt)
+        .filter(|opt| passes_filters(opt, config))
+        .filter_map(|opt| {
+            // a score of 0 is no match, e.g. a few query characters scattered over the label
+            let rank = candidate_score(query, opt, scorer, config).filter(|&rank| rank > 0)?;
+            let score = boosted(rank / RANK_SCALE, opt);
+            let key = match tier_query.as_deref() {
+                Some(q) => tiered_score(score, match_tier(q, opt, config)),
+                None => score,
+            };
+            // options scoring 0 were dropped above, so only a pin that matches is lifted
+            let pin = if config.pinned.contains(&opt.text) {
+                PINNED_STEP
+            } else {
+                0
+            };
+            // the fraction of a point below the whole points orders the options they would tie
+            Some(((key + pin) * RANK_SCALE + rank % RANK_SCALE, opt))
+        });
+    let best = best_per_text(scored, count);
+    (best, timed_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::{CompositeScorer, EditDistanceScorer, FuzzyScorer, SemanticScorer};
+    use crate::structs::{Frecency, ScoringConfig};
+    use std::time::Duration;
+
+    fn options(texts: &[&str]) -> Vec<Candidate> {
+        texts
+            .iter()
+            .map(|text| Candidate::new(text.to_string(), None, Vec::new()))
+            .collect()
+    }
+
+    fn texts(query: &str, options: &[Candidate], config: &SearchConfig) -> Vec<String> {
+        let scoring = ScoringConfig::default();
+        let scorer = FuzzyScorer { config: &scoring };
+        get_suggestions(query, options, &scorer, config)
+            .suggestions
+            .into_iter()
+            .map(|sug| sug.text)
+            .collect()
+    }
+
+    #[test]
+    fn taking_the_ranked_suggestions_gives_the_leaders() {
+        let texts: Vec<String> = (0..10_000)
+            .map(|i| format!("item {}", i * 7919 % 10_007))
+            .collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let options = options(&texts);
+        let scoring = ScoringConfig::default();
+        let scorer = FuzzyScorer { config: &scoring };
+        let config = SearchConfig {
+            limit: 1000,
+            ..Default::default()
+        };
+        let all = get_suggestions("item 99", &options, &scorer, &config).suggestions;
+        let (ranked, timed_out) = ranked_suggestions("item 99", &options, &scorer, &config);
+        let top: Vec<(String, usize)> = ranked.take(3).map(|sug| (sug.text, sug.score)).collect();
+        let leaders: Vec<(String, usize)> = all[..3]
+            .iter()
+            .map(|sug| (sug.text.clone(), sug.score))
+            .collect();
+        assert!(!timed_out);
+        assert_eq!(top, leaders);
+        assert_eq!(top[0].0, "item 99");
+    }
+
+    #[test]
+    fn frecency_only_boosts_matches() {
+        let options = options(&["apple", "zzz", "application"]);
+        let mut frecency = Frecency::default();
+        let now = structs::unix_now();
+        for _ in 0..10 {
+            frecency.record("zzz", now);
+            frecency.record("application", now);
+        }
+        let config = SearchConfig {
+            frecency: Some(frecency),
+            ..Default::default()
+        };
+        assert_eq!(texts("app", &options, &config), ["application", "apple"]);
+    }
+
+    #[test]
+    fn matching_pins_come_first_and_others_are_hidden() {
+        let options = options(&["apple", "zzz", "snapper"]);
+        let config = SearchConfig {
+            pinned: ["zzz", "snapper"].map(String::from).into(),
+            ..Default::default()
+        };
+        assert_eq!(texts("app", &options, &config), ["snapper", "apple"]);
+        // still first when sorted by name or browsing
+        let by_name = SearchConfig {
+            sort: SortOrder::Name,
+            pinned: config.pinned.clone(),
+            ..Default::default()
+        };
+        assert_eq!(texts("app", &options, &by_name), ["snapper", "apple"]);
+        let browsing = SearchConfig {
+            browse: true,
+            ..config
+        };
+        assert_eq!(texts("", &options, &browsing), ["zzz", "snapper", "apple"]);
+    }
+
+    #[test]
+    fn best_per_text_keeps_the_best_line_of_each_text() {
+        let options = options(&["a", "b", "a", "c", "b"]);
+        let scored = [3, 5, 7, 1, 2].into_iter().zip(&options);
+        let best: Vec<&str> = best_per_text(scored, 2)
+            .into_iter()
+            .map(|opt| opt.text.as_str())
+            .collect();
+        assert_eq!(best, ["a", "b"]);
+
+        // ties go to the text seen first, even after its better line replaced it
+        let scored = [1, 4, 4, 4].into_iter().zip(&options[..4]);
+        let best: Vec<&str> = best_per_text(scored, 3)
+            .into_iter()
+            .map(|opt| opt.text.as_str())
+            .collect();
+        assert_eq!(best, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn suffix_and_fields_are_scored_with_their_prepared_forms() {
+        let scoring = ScoringConfig::default();
+        let scorer = FuzzyScorer { config: &scoring };
+        let config = SearchConfig {
+            match_suffix: true,
+            ..Default::default()
+        };
+        let suffixed = Candidate::new("src/main.rs:12: Ünïcode".to_string(), None, Vec::new());
+        let sug = score_candidate("ünï", &suffixed, &scorer, &config).unwrap();
+        let offset = suffixed.text.rfind(':').unwrap() + 2;
+        assert_eq!(sug.match_indices[0], offset);
+        assert_eq!(
+            candidate_score("ünï", &suffixed, &scorer, &config),
+            Some(sug.score * RANK_SCALE)
+        );
+
+        let described = Candidate::new(
+            "firefox".to_string(),
+            Some("Wëb browser".to_string()),
+            vec!["internet".to_string()],
+        );
+        let sug = score_candidate("wëb", &described, &scorer, &SearchConfig::default()).unwrap();
+        assert_eq!(sug.matched_field, Some(MatchedField::Description));
+        let alone = scorer.score("wëb", "Wëb browser").unwrap().score;
+        assert_eq!(sug.score, alone / 2);
+        let sug =
+            score_candidate("internet", &described, &scorer, &SearchConfig::default()).unwrap();
+        assert_eq!(sug.matched_field, Some(MatchedField::Keyword));
+    }
+
+    #[test]
+    fn options_sharing_a_few_characters_are_not_listed() {
+        let options = options(&["extra", "jazz", "quiz", "apple"]);
+        assert!(texts("qqqqxzj", &options, &SearchConfig::default()).is_empty());
+        assert_eq!(texts("app", &options, &SearchConfig::default()), ["apple"]);
+    }
+
+    #[test]
+    fn options_without_an_embedding_are_ranked_lexically() {
+        let options = options(&["apple", "banana", "applesauce", "grape"]);
+        let embeddings = HashMap::from([
+            ("apple".to_string(), vec![1.0, 0.0]),
+            ("banana".to_string(), vec![0.6, 0.8]),
+        ]);
+        let scoring = ScoringConfig::default();
+        let scorer = SemanticScorer {
+            query_embedding: &[1.0, 0.0],
+            option_embeddings: &embeddings,
+            normalized: true,
+            config: &scoring,
+            threshold: 0.0,
+        };
+        let results = get_suggestions("apple", &options, &scorer, &SearchConfig::default());
+        let ranked: Vec<(&str, usize)> = results
+            .suggestions
+            .iter()
+            .map(|sug| (sug.text.as_str(), sug.score))
+            .collect();
+        let lexical = algorithms::fuzzy_match("apple", "applesauce", &scoring).unwrap();
+        assert_eq!(
+            ranked,
+            [
+                ("apple", 1000),
+                ("banana", 600),
+                ("applesauce", lexical.score)
+            ]
+        );
+    }
+
+    #[test]
+    fn scorers_are_swapped_and_composed_behind_the_trait() {
+        let options = options(&["hello", "hello world", "help me", "halo"]);
+        let scoring = ScoringConfig::default();
+        let config = SearchConfig::default();
+        let ranked = |scorer: &dyn Scorer| -> Vec<String> {
+            get_suggestions("helo", &options, scorer, &config)
+                .suggestions
+                .into_iter()
+                .map(|sug| sug.text)
+                .collect()
+        };
+        let fuzzy = FuzzyScorer { config: &scoring };
+        let edits = EditDistanceScorer::new("helo", 2);
+        assert_eq!(ranked(&fuzzy), ["hello", "halo", "hello world"]);
+        assert_eq!(ranked(&edits), ["hello", "halo"]);
+        // only what both match
+        let both = CompositeScorer::new(vec![(Box::new(fuzzy), 1.0), (Box::new(edits), 1.0)]);
+        assert_eq!(ranked(&both), ["hello", "halo"]);
+    }
+
+    #[test]
+    fn empty_query_browses_the_first_options_in_order() {
+        let options = options(&["cherry", "apple", "banana", "apple", "date"]);
+        let config = SearchConfig {
+            browse: true,
+            limit: 3,
+            ..Default::default()
+        };
+        let results = get_suggestions(
+            "",
+            &options,
+            &FuzzyScorer {
+                config: &ScoringConfig::default(),
+            },
+            &config,
+        );
+        let listed: Vec<(&str, usize)> = results
+            .suggestions
+            .iter()
+            .map(|sug| (sug.text.as_str(), sug.score))
+            .collect();
+        assert_eq!(listed, [("cherry", 0), ("apple", 0), ("banana", 0)]);
+        assert!(texts("", &options, &SearchConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn hidden_keyword_matches_return_the_label_without_highlights() {
+        let options = vec![
+            Candidate::new(
+                "Firefox".to_string(),
+                None,
+                vec!["browser".to_string(), "web".to_string()],
+            ),
+            Candidate::new("Files".to_string(), None, vec!["explorer".to_string()]),
+        ];
+        let scoring = ScoringConfig::default();
+        let scorer = FuzzyScorer { config: &scoring };
+        let results = get_suggestions("brow", &options, &scorer, &SearchConfig::default());
+        let [sug] = &results.suggestions[..] else {
+            panic!("expected one suggestion");
+        };
+        assert_eq!(sug.text, "Firefox");
+        assert!(matches!(sug.matched_field, Some(MatchedField::Keyword)));
+        assert!(sug.match_indices.is_empty());
+        // a label match is highlighted
+        let results = get_suggestions("fi", &options, &scorer, &SearchConfig::default());
+        assert!(matches!(
+            results.suggestions[0].matched_field,
+            Some(MatchedField::Label)
+        ));
+        assert_eq!(results.suggestions[0].match_indices, [0, 1]);
+    }
+
+    #[test]
+    fn match_suffix_scores_the_text_after_the_last_colon() {
+        let line = "src/main.rs:42: let x = 1";
+        let options = options(&[line, "src/lib.rs:7: fn main() {}"]);
+        let scoring = ScoringConfig::default();
+        let scorer = FuzzyScorer { config: &scoring };
+        let config = SearchConfig {
+            match_suffix: true,
+            ..Default::default()
+        };
+        let results = get_suggestions("letx", &options, &scorer, &config);
+        let [sug] = &results.suggestions[..] else {
+            panic!("expected one suggestion");
+        };
+        // the whole line is returned, highlighted at the suffix
+        assert_eq!(sug.text, line);
+        assert_eq!(sug.match_indices, [16, 17, 18, 20]);
+        // the path no longer matches
+        assert!(
+            get_suggestions("mainrs", &options, &scorer, &config)
+                .suggestions
+                .is_empty()
+        );
+        assert_eq!(texts("mainrs", &options, &SearchConfig::default()).len(), 1);
+    }
+
+    #[test]
+    fn description_matches_count_for_half() {
+        let options = vec![
+            Candidate::new(
+                "Firefox".to_string(),
+                Some("web browser".to_string()),
+                Vec::new(),
+            ),
+            Candidate::new("browser".to_string(), None, Vec::new()),
+        ];
+        let scoring = ScoringConfig::default();
+        let scorer = FuzzyScorer { config: &scoring };
+        let results = get_suggestions("browser", &options, &scorer, &SearchConfig::default());
+        let [label, description] = &results.suggestions[..] else {
+            panic!("expected two suggestions");
+        };
+        assert_eq!(label.text, "browser");
+        assert_eq!(description.text, "Firefox");
+        assert!(matches!(
+            description.matched_field,
+            Some(MatchedField::Description)
+        ));
+        let alone = algorithms::fuzzy_match("browser", "web browser", &scoring).unwrap();
+        assert_eq!(description.score, alone.score / 2);
+        assert_eq!(description.description.as_deref(), Some("web browser"));
+    }
+
+    #[test]
+    fn regex_filter_excludes_options_before_scoring() {
+        let options = options(&["src/main.rs", "src/main.py", "tests/main.rs", "README.md"]);
+        let config = SearchConfig {
+            regex: Some(regex::Regex::new(r"\.rs$").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            texts("main", &options, &config),
+            ["src/main.rs", "tests/main.rs"]
+        );
+        let config = SearchConfig {
+            regex: Some(regex::Regex::new("^src/").unwrap()),
+            browse: true,
+            ..Default::default()
+        };
+        assert_eq!(texts("", &options, &config), ["src/main.rs", "src/main.py"]);
+    }
+
+    #[test]
+    fn glob_filter_excludes_options_before_scoring() {
+        let options = options(&["src/main.rs", "src/main.py", "main.rs", "docs/main.md"]);
+        let config = SearchConfig {
+            glob: Some(globset::Glob::new("*.rs").unwrap().compile_matcher()),
+            ..Default::default()
+        };
+        assert_eq!(texts("main", &options, &config), ["main.rs", "src/main.rs"]);
+        let config = SearchConfig {
+            glob: Some(globset::Glob::new("src/*").unwrap().compile_matcher()),
+            browse: true,
+            ..Default::default()
+        };
+        assert_eq!(texts("", &options, &config), ["src/main.rs", "src/main.py"]);
+    }
+
+    #[test]
+    fn length_filters_count_chars() {
+        // "ñandú" is 5 chars but 7 bytes
+        let options = options(&["and", "ñandú", "andante", "sandwiches"]);
+        let config = SearchConfig {
+            min_len: Some(4),
+            max_len: Some(7),
+            ..Default::default()
+        };
+        assert_eq!(texts("and", &options, &config), ["andante", "ñandú"]);
+        let config = SearchConfig {
+            max_len: Some(5),
+            browse: true,
+            ..Default::default()
+        };
+        assert_eq!(texts("", &options, &config), ["and", "ñandú"]);
+    }
+
+    #[test]
+    fn empty_semantic_query_is_not_ranked() {
+        // the embedding an empty query would get favors the last option
+        let options = options(&["apple", "banana", "cherry"]);
+        let embeddings = HashMap::from([
+            ("apple".to_string(), vec![0.0, 1.0]),
+            ("banana".to_string(), vec![0.6, 0.8]),
+            ("cherry".to_string(), vec![1.0, 0.0]),
+        ]);
+        let scoring = ScoringConfig::default();
+        let scorer = CompositeScorer::new(vec![
+            (Box::new(FuzzyScorer { config: &scoring }), 0.5),
+            (
+                Box::new(SemanticScorer {
+                    query_embedding: &[1.0, 0.0],
+                    option_embeddings: &embeddings,
+                    normalized: true,
+                    config: &scoring,
+                    threshold: 0.0,
+                }),
+                0.5,
+            ),
+        ]);
+        let config = SearchConfig {
+            browse: true,
+            ..Default::default()
+        };
+        let results = get_suggestions("", &options, &scorer, &config);
+        let listed: Vec<(&str, usize)> = results
+            .suggestions
+            .iter()
+            .map(|sug| (sug.text.as_str(), sug.score))
+            .collect();
+        assert_eq!(listed, [("apple", 0), ("banana", 0), ("cherry", 0)]);
+        assert!(
+            get_suggestions("", &options, &scorer, &SearchConfig::default())
+                .suggestions
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn max_scan_bounds_the_scored_options() {
+        struct Counting<'a> {
+            scorer: FuzzyScorer<'a>,
+            scored: std::cell::Cell<usize>,
+        }
+        impl Scorer for Counting<'_> {
+            fn score(&self, query: &str, candidate: &str) -> Option<Suggestion> {
+                self.scored.set(self.scored.get() + 1);
+                self.scorer.score(query, candidate)
+            }
+        }
+        let texts: Vec<String> = (0..100).map(|i| format!("option {i}")).collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let options = options(&texts);
+        let scoring = ScoringConfig::default();
+        let scorer = Counting {
+            scorer: FuzzyScorer { config: &scoring },
+            scored: Default::default(),
+        };
+        let config = SearchConfig {
+            max_scan: Some(10),
+            ..Default::default()
+        };
+        let (best, _) = best_candidates("option 9", &options, &scorer, &config, 20);
+        assert_eq!(scorer.scored.get(), 10);
+        // "option 99" and the others past the first 10 aren't considered
+        let best: Vec<&str> = best.iter().map(|opt| opt.text.as_str()).collect();
+        assert_eq!(best[0], "option 9");
+        assert!(best.iter().all(|text| texts[..10].contains(text)));
+    }
+
+    #[test]
+    fn duplicate_texts_are_one_row_with_the_best_score() {
+        // the same option from two word files, matched through different fields
+        let options = vec![
+            Candidate::new("pie".to_string(), Some("apple pie".to_string()), Vec::new()),
+            Candidate::new("apple".to_string(), None, Vec::new()),
+            Candidate::new("pie".to_string(), None, vec!["apple".to_string()]),
+        ];
+        let scoring = ScoringConfig::default();
+        let scorer = FuzzyScorer { config: &scoring };
+        let scores = |options: &[Candidate]| -> Vec<(String, usize)> {
+            get_suggestions("apple", options, &scorer, &SearchConfig::default())
+                .suggestions
+                .into_iter()
+                .map(|sug| (sug.text, sug.score))
+                .collect()
+        };
+        let by_description = scores(&options[..1])[0].1;
+        let by_keyword = scores(&options[2..])[0].1;
+        assert_ne!(by_description, by_keyword);
+        let rows = scores(&options);
+        let pies: Vec<usize> = rows
+            .iter()
+            .filter(|(text, _)| text == "pie")
+            .map(|&(_, score)| score)
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(pies, [by_description.max(by_keyword)]);
+    }
+
+    #[test]
+    fn max_edit_distance_filters_and_ranks_by_distance() {
+        let options = options(&["spelling", "spieling", "spellings", "spell", "selling out"]);
+        let scorer = EditDistanceScorer::new("speling", 2);
+        let results = get_suggestions("speling", &options, &scorer, &SearchConfig::default());
+        let ranked: Vec<(&str, usize)> = results
+            .suggestions
+            .iter()
+            .map(|sug| (sug.text.as_str(), sug.score))
+            .collect();
+        // one edit away, then two; "spell" and "selling out" are further off
+        assert_eq!(
+            ranked,
+            [("spelling", 666), ("spieling", 666), ("spellings", 333)]
+        );
+    }
+
+    #[test]
+    fn semantic_threshold_drops_unrelated_options() {
+        let options = options(&["near", "far", "opposite"]);
+        let embeddings = HashMap::from([
+            ("near".to_string(), vec![0.8, 0.6]),
+            ("far".to_string(), vec![0.3, 0.954]),
+            ("opposite".to_string(), vec![-1.0, 0.0]),
+        ]);
+        let scoring = ScoringConfig::default();
+        let scorer = |threshold| SemanticScorer {
+            query_embedding: &[1.0, 0.0],
+            option_embeddings: &embeddings,
+            normalized: true,
+            config: &scoring,
+            threshold,
+        };
+        let texts = |threshold| -> Vec<String> {
+            get_suggestions("q", &options, &scorer(threshold), &SearchConfig::default())
+                .suggestions
+                .into_iter()
+                .map(|sug| sug.text)
+                .collect()
+        };
+        // a negative similarity never scores
+        assert_eq!(texts(0.0), ["near", "far"]);
+        assert_eq!(texts(0.5), ["near"]);
+    }
+
+    #[test]
+    fn matches_sort_by_score_or_name_in_either_direction() {
+        let options = options(&["pear", "peach", "apple", "spear", "grape"]);
+        let sorted = |sort, reverse| {
+            let config = SearchConfig {
+                sort,
+                reverse,
+                ..Default::default()
+            };
+            texts("pea", &options, &config)
+        };
+        let by_score = sorted(SortOrder::Score, false);
+        assert_eq!(by_score, ["pear", "peach", "spear"]);
+        assert_eq!(sorted(SortOrder::Score, true), ["spear", "peach", "pear"]);
+        // still only the matches, "apple" and "grape" share too little
+        assert_eq!(sorted(SortOrder::Name, false), ["peach", "pear", "spear"]);
+        assert_eq!(sorted(SortOrder::Name, true), ["spear", "pear", "peach"]);
+        assert!(matches!("name".parse(), Ok(SortOrder::Name)));
+        assert!("size".parse::<SortOrder>().is_err());
+    }
+
+    #[test]
+    fn suggestions_tell_which_field_matched() {
+        let options = vec![
+            Candidate::new("terminal".to_string(), None, Vec::new()),
+            Candidate::new(
+                "Alacritty".to_string(),
+                Some("a terminal emulator".to_string()),
+                Vec::new(),
+            ),
+            Candidate::new("kitty".to_string(), None, vec!["terminal".to_string()]),
+        ];
+        let scoring = ScoringConfig::default();
+        let scorer = FuzzyScorer { config: &scoring };
+        let results = get_suggestions("terminal", &options, &scorer, &SearchConfig::default());
+        let fields: Vec<(&str, Option<MatchedField>)> = results
+            .suggestions
+            .iter()
+            .map(|sug| (sug.text.as_str(), sug.matched_field))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("terminal", Some(MatchedField::Label)),
+                ("kitty", Some(MatchedField::Keyword)),
+                ("Alacritty", Some(MatchedField::Description))
+            ]
+        );
+        // browsing matches nothing
+        let config = SearchConfig {
+            browse: true,
+            ..Default::default()
+        };
+        let browsed = get_suggestions("", &options, &scorer, &config);
+        assert!(
+            browsed
+                .suggestions
+                .iter()
+                .all(|sug| sug.matched_field.is_none())
+        );
+    }
+
+    #[test]
+    fn slow_scorers_stop_at_the_timeout_with_the_best_so_far() {
+        struct Slow<'a>(FuzzyScorer<'a>);
+        impl Scorer for Slow<'_> {
+            fn score(&self, query: &str, candidate: &str) -> Option<Suggestion> {
+                std::thread::sleep(Duration::from_micros(50));
+                self.0.score(query, candidate)
+            }
+        }
+        // 50µs each, about half a second for all of them
+        let texts: Vec<String> = (0..10_000).map(|i| format!("item {i}")).collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let options = options(&texts);
+        let scoring = ScoringConfig::default();
+        let scorer = Slow(FuzzyScorer { config: &scoring });
+        let config = SearchConfig {
+            timeout: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let results = get_suggestions("item 99", &options, &scorer, &config);
+        assert!(started.elapsed() < Duration::from_millis(400));
+        assert!(results.timed_out);
+        // the options scored until then are still ranked, the later ones were never reached
+        assert_eq!(results.suggestions[0].text, "item 99");
+        assert!(
+            !results
+                .suggestions
+                .iter()
+                .any(|sug| sug.text == "item 9999")
+        );
+        let config = SearchConfig {
+            timeout: None,
+            ..config
+        };
+        let results = get_suggestions("item 99", &options[..100], &scorer, &config);
+        assert!(!results.timed_out);
+        assert_eq!(results.suggestions[0].text, "item 99");
+    }
+
+    #[test]
+    fn tiers_group_exact_prefix_substring_then_fuzzy() {
+        let late = "the quick brown fox jumps over the lazy dog and the scat";
+        let options = options(&["c a t", late, "Category", "cart", "cat"]);
+        let scoring = ScoringConfig::default();
+        let scorer = FuzzyScorer { config: &scoring };
+        let ranked = |tiers| {
+            let config = SearchConfig {
+                tiers,
+                ..Default::default()
+            };
+            get_suggestions("cat", &options, &scorer, &config).suggestions
+        };
+        // the substring far into a long label scores below the spaced out initials
+        let untiered: Vec<_> = ranked(false).into_iter().map(|sug| sug.text).collect();
+        assert_eq!(untiered, ["cat", "Category", "c a t", late, "cart"]);
+        assert!(ranked(false).iter().all(|sug| sug.tier.is_none()));
+        let tiered: Vec<_> = ranked(true)
+            .into_iter()
+            .map(|sug| (sug.text, sug.tier.unwrap()))
+            .collect();
+        assert_eq!(
+            tiered,
+            [
+                ("cat".to_string(), MatchTier::Exact),
+                ("Category".to_string(), MatchTier::Prefix),
+                (late.to_string(), MatchTier::Substring),
+                ("c a t".to_string(), MatchTier::Fuzzy),
+                ("cart".to_string(), MatchTier::Fuzzy),
+            ]
+        );
+    }
+
+    #[test]
+    fn cutoff_drops_the_tail_after_the_first_cliff() {
+        let scored = |scores: &[usize]| -> Vec<Suggestion> {
+            scores
+                .iter()
+                .map(|&score| Suggestion {
+                    score,
+                    ..Default::default()
+                })
+                .collect()
+        };
+        let cut = |mut suggestions: Vec<Suggestion>, gap| {
+            cut_at_gap(&mut suggestions, gap);
+            suggestions.iter().map(|sug| sug.score).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            cut(scored(&[900, 880, 850, 300, 290, 100]), 0.5),
+            [900, 880, 850]
+        );
+        // an even decline has no cliff
+        assert_eq!(
+            cut(scored(&[900, 800, 700, 600]), 0.5),
+            [900, 800, 700, 600]
+        );
+        // a more sensitive gap cuts at a smaller drop
+        assert_eq!(cut(scored(&[900, 800, 700, 600]), 0.12), [900, 800]);
+        // only the first cliff counts
+        assert_eq!(cut(scored(&[1000, 400, 100]), 0.5), [1000]);
+        assert!(cut(Vec::new(), 0.5).is_empty());
+
+        // a weak pin stays and the cliff is looked for after it
+        let mut suggestions = scored(&[50, 900, 300]);
+        suggestions[0].pinned = true;
+        assert_eq!(cut(suggestions, 0.5), [50, 900]);
+    }
+
+    #[test]
+    fn close_similarities_rank_by_the_full_cosine() {
+        // both show as 900 points, the cosines only differ in the 4th decimal
+        let unit = |cos: f32| vec![cos, (1.0 - cos * cos).sqrt()];
+        let embeddings = HashMap::from([
+            ("lower".to_string(), unit(0.9001)),
+            ("higher".to_string(), unit(0.9004)),
+        ]);
+        let scoring = ScoringConfig::default();
+        let scorer = SemanticScorer {
+            query_embedding: &[1.0, 0.0],
+            option_embeddings: &embeddings,
+            normalized: true,
+            config: &scoring,
+            threshold: 0.0,
+        };
+        for order in [["lower", "higher"], ["higher", "lower"]] {
+            let results = get_suggestions("q", &options(&order), &scorer, &SearchConfig::default());
+            let ranked: Vec<(&str, usize)> = results
+                .suggestions
+                .iter()
+                .map(|sug| (sug.text.as_str(), sug.score))
+                .collect();
+            assert_eq!(ranked, [("higher", 900), ("lower", 900)]);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::algorithms::Scorer;
+use crate::search::{SearchResults, get_suggestions};
+use crate::structs::{Candidate, SearchConfig};
+
+pub type UpdateCallback<'a> = Box<dyn FnMut(&SearchResults) + 'a>;
+
+// The search engine without a presentation: the options, the scorer and the current query with
+// its results, for frontends other than the terminal interface. Every change of the query searches
+// again and passes the new results to the callback, if one is set.
+pub struct SearchSession<'a> {
+    options: Vec<Candidate>,
+    scorer: Box<dyn Scorer + 'a>,
+    config: SearchConfig,
+    query: String,
+    results: SearchResults,
+    on_update: Option<UpdateCallback<'a>>,
+}
+
+impl<'a> SearchSession<'a> {
+    // starts with an empty query, which lists the options with browse and nothing otherwise
+    pub fn new(options: Vec<Candidate>, scorer: impl Scorer + 'a, config: SearchConfig) -> Self {
+        let results = get_suggestions("", &options, &scorer, &config);
+        Self {
+            options,
+            scorer: Box::new(scorer),
+            config,
+            query: String::new(),
+            results,
+            on_update: None,
+        }
+    }
+
+    // the same query isn't searched again, so this can be called on every input event
+    pub fn set_query(&mut self, query: &str) -> &SearchResults {
+        if query != self.query {
+            self.query = query.to_string();
+            self.search();
+        }
+        &self.results
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn results(&self) -> &SearchResults {
+        &self.results
+    }
+
+    // replaces the previous callback
+    pub fn on_update(&mut self, callback: impl FnMut(&SearchResults) + 'a) {
+        self.on_update = Some(Box::new(callback));
+    }
+
+    // e.g. after a timed out search, or when the scorer depends on state that changed
+    pub fn refresh(&mut self) -> &SearchResults {
+        self.search();
+        &self.results
+    }
+
+    fn search(&mut self) {
+        self.results = get_suggestions(&self.query, &self.options, &*self.scorer, &self.config);
+        if let Some(callback) = &mut self.on_update {
+            callback(&self.results);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::FuzzyScorer;
+    use crate::structs::ScoringConfig;
+    use std::cell::RefCell;
+
+    fn session(scoring: &ScoringConfig) -> SearchSession<'_> {
+        let options = ["apple", "banana", "apricot", "cherry"]
+            .iter()
+            .map(|text| Candidate::new(text.to_string(), None, Vec::new()))
+            .collect();
+        let config = SearchConfig {
+            limit: 10,
+            browse: true,
+            ..Default::default()
+        };
+        SearchSession::new(options, FuzzyScorer { config: scoring }, config)
+    }
+
+    fn texts(results: &SearchResults) -> Vec<&str> {
+        results
+            .suggestions
+            .iter()
+            .map(|sug| sug.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn queries_drive_the_results() {
+        let scoring = ScoringConfig::default();
+        let mut session = session(&scoring);
+        assert_eq!(session.query(), "");
+        assert_eq!(
+            texts(session.results()),
+            ["apple", "banana", "apricot", "cherry"]
+        );
+        assert_eq!(texts(session.set_query("ap")), ["apple", "apricot"]);
+        assert_eq!(texts(session.set_query("cherr")), ["cherry"]);
+        assert!(session.set_query("xyz").suggestions.is_empty());
+        assert_eq!(session.query(), "xyz");
+        assert_eq!(texts(session.set_query("")).len(), 4);
+    }
+
+    #[test]
+    fn callback_sees_every_new_result_set_once() {
+        let scoring = ScoringConfig::default();
+        let seen = RefCell::new(Vec::new());
+        {
+            let mut session = session(&scoring);
+            session.on_update(|results| seen.borrow_mut().push(results.suggestions.len()));
+            session.set_query("ap");
+            // the same query isn't searched again
+            session.set_query("ap");
+            session.set_query("apr");
+            session.refresh();
+        }
+        assert_eq!(seen.into_inner(), [2, 1, 1]);
+    }
+}
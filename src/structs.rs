@@ -1,11 +1,677 @@
+use crate::algorithms::{self, MAX_SCORE, Tokenizer};
+use globset::GlobMatcher;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Default)]
 pub struct Suggestion {
     pub text: String,
     pub match_indices: Vec<usize>,
     pub score: usize,
+    // char positions in the query that could not be matched against this suggestion
+    pub unmatched_query_indices: Vec<usize>,
+    // shown dimmed after the text
+    pub description: Option<String>,
+    // drawn before the text, not part of it
+    pub icon: Option<String>,
+    // the text with its original ANSI escapes, match indices still refer to text
+    pub display: Option<String>,
+    // metadata drawn in aligned columns after the text
+    pub columns: Vec<String>,
+    // ranked by embedding similarity without any lexical match to highlight
+    pub semantic_only: bool,
+    // the field the score comes from, None when nothing was scored (e.g. browsing)
+    pub matched_field: Option<MatchedField>,
+    // how directly the label matches the query, only set with SearchConfig::tiers
+    pub tier: Option<MatchTier>,
+    // one of SearchConfig::pinned, listed above the other suggestions
+    pub pinned: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchedField {
+    Label,
+    Description,
+    Keyword,
+}
+
+// in ranking order, with tiers every match of a tier ranks above those of the next
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchTier {
+    Exact,
+    Prefix,
+    Substring,
+    Fuzzy,
+}
+
+impl Suggestion {
+    // every scorer clamps to MAX_SCORE, so this is always within 0.0 - 1.0
+    pub fn normalized_score(&self) -> f32 {
+        self.score.min(MAX_SCORE) as f32 / MAX_SCORE as f32
+    }
+}
+
+#[derive(Clone)]
+pub struct Candidate {
+    // label that is displayed and returned
+    pub text: String,
+    // shown dimmed after the label, matches on it count for less than matches on the label
+    pub description: Option<String>,
+    // hidden fields that are matched against but never displayed
+    pub keywords: Vec<String>,
+    // leading glyph drawn before the label, never matched against
+    pub icon: Option<String>,
+    // the label as read, with its ANSI escapes, drawn in place of text when set
+    pub display: Option<String>,
+    // displayed in a table next to the label, never matched against
+    pub columns: Vec<String>,
+    pub prepared: PreparedCandidate,
+    // the same for the text after the last ':' (see SearchConfig::match_suffix), the description
+    // and every keyword, which are scored on every keystroke like the label
+    pub prepared_suffix: Option<PreparedCandidate>,
+    pub prepared_description: Option<PreparedCandidate>,
+    pub prepared_keywords: Vec<PreparedCandidate>,
+}
+
+impl Candidate {
+    pub fn new(text: String, description: Option<String>, keywords: Vec<String>) -> Self {
+        let prepared = PreparedCandidate::new(&text);
+        let prepared_suffix = text
+            .rfind(':')
+            .map(|pos| PreparedCandidate::new(&text[pos + 1..]));
+        let prepared_description = description.as_deref().map(PreparedCandidate::new);
+        let prepared_keywords = keywords
+            .iter()
+            .map(|kw| PreparedCandidate::new(kw))
+            .collect();
+        Self {
+            text,
+            description,
+            keywords,
+            icon: None,
+            display: None,
+            columns: Vec::new(),
+            prepared,
+            prepared_suffix,
+            prepared_description,
+            prepared_keywords,
+        }
+    }
+
+    // prepares the forms the scoring config matches on for the label and every field, see
+    // PreparedCandidate::prepare_forms
+    pub fn prepare_forms(&mut self, config: &ScoringConfig) {
+        let text = &self.text;
+        self.prepared.prepare_forms(text, config);
+        if let (Some(pos), Some(suffix)) = (text.rfind(':'), &mut self.prepared_suffix) {
+            suffix.prepare_forms(&text[pos + 1..], config);
+        }
+        if let (Some(desc), Some(prepared)) = (&self.description, &mut self.prepared_description) {
+            prepared.prepare_forms(desc, config);
+        }
+        for (keyword, prepared) in self.keywords.iter().zip(&mut self.prepared_keywords) {
+            prepared.prepare_forms(keyword, config);
+        }
+    }
+}
+
+// which tab separated fields come with the label on each line
+#[derive(Clone, Copy, Default)]
+pub struct InputFormat {
+    // a leading field before the label
+    pub icons: bool,
+    pub descriptions: bool,
+    pub keywords: bool,
+    // strip ANSI escapes before matching but draw the labels with them
+    pub raw: bool,
+    // every field after the label is a column
+    pub columns: bool,
+}
+
+// query independent features of a candidate text, computed once when the options are loaded
+// instead of on every keystroke
+#[derive(Clone)]
+pub struct PreparedCandidate {
+    // of the NFC form
+    pub lowercase: String,
+    // pure ASCII text, where bytes are chars and the scoring can take its byte based fast path
+    pub ascii: bool,
+    // the NFC form with its offsets into the text, when the text isn't NFC already
+    pub nfc: Option<(String, Vec<usize>)>,
+    // the algorithms::case_fold form of the NFC form with its offsets, only prepared with
+    // ScoringConfig::case_folding, see prepare_forms
+    pub folded: Option<(String, Vec<usize>)>,
+    // only prepared with ScoringConfig::slug
+    pub slug: Option<Box<PreparedSlug>>,
+}
+
+impl PreparedCandidate {
+    pub fn new(text: &str) -> Self {
+        let ascii = text.is_ascii();
+        if ascii {
+            return Self {
+                lowercase: text.to_ascii_lowercase(),
+                ascii,
+                nfc: None,
+                folded: None,
+                slug: None,
+            };
+        }
+        let nfc = algorithms::nfc(text);
+        Self {
+            lowercase: nfc
+                .as_ref()
+                .map_or(text, |(c, _)| c.as_str())
+                .to_lowercase(),
+            ascii,
+            nfc,
+            folded: None,
+            slug: None,
+        }
+    }
+
+    // The forms only some scoring configs match on, which would otherwise be computed for every
+    // option on every keystroke. They're left out by default to keep the options small, the
+    // scoring computes a missing one on the fly.
+    pub fn prepare_forms(&mut self, text: &str, config: &ScoringConfig) {
+        // the slug forms are what is matched then, with the folded form of their own
+        if config.slug {
+            self.slug = Some(Box::new(PreparedSlug::new(text, config)));
+        } else if config.case_folding {
+            let nfc = self.nfc.as_ref().map_or(text, |(c, _)| c.as_str());
+            self.folded = Some(algorithms::case_fold(nfc));
+        }
+    }
+}
+
+// the slug form of a text with the offsets of algorithms::slug and its own prepared features
+#[derive(Clone)]
+pub struct PreparedSlug {
+    pub text: String,
+    pub offsets: Vec<usize>,
+    pub prepared: PreparedCandidate,
+}
+
+impl PreparedSlug {
+    pub fn new(text: &str, config: &ScoringConfig) -> Self {
+        let (slug, offsets) = algorithms::slug(text);
+        let mut prepared = PreparedCandidate::new(&slug);
+        if config.case_folding {
+            prepared.folded = Some(algorithms::case_fold(&slug));
+        }
+        Self {
+            prepared,
+            text: slug,
+            offsets,
+        }
+    }
+}
+
+pub struct SearchConfig {
+    // maximum number of suggestions returned
+    pub limit: usize,
+    // list options in file order while the query is empty
+    pub browse: bool,
+    // only match the text after the last ':' of each option, e.g. grep -n output
+    pub match_suffix: bool,
+    // only options matching this are ranked
+    pub regex: Option<Regex>,
+    // like regex, a whole option has to match
+    pub glob: Option<GlobMatcher>,
+    // inclusive bounds on the option length in chars
+    pub min_len: Option<usize>,
+    pub max_len: Option<usize>,
+    // only the first this many options are considered, as a latency cap
+    pub max_scan: Option<usize>,
+    // boosts options that were selected often and recently
+    pub frecency: Option<Frecency>,
+    // order of the returned suggestions, which are always the best scoring ones
+    pub sort: SortOrder,
+    // lowest score or last name first
+    pub reverse: bool,
+    // exact, prefix and substring matches of the label rank above the other matches, by score
+    // within each tier
+    pub tiers: bool,
+    // texts of options listed first whenever they match, whatever their score
+    pub pinned: HashSet<String>,
+    // the ranked matches end before the first one scoring less than this fraction below the one
+    // above it, dropping the weak tail after a clear break in the scores
+    pub cutoff_gap: Option<f32>,
+    // scoring stops after this long, keeping the best of the options scored until then
+    pub timeout: Option<Duration>,
+}
+
+impl Default for SearchConfig {
+    // no filters, ranked by score, with as many suggestions as the cli shows without a terminal
+    fn default() -> Self {
+        Self {
+            limit: 20,
+            browse: false,
+            match_suffix: false,
+            regex: None,
+            glob: None,
+            min_len: None,
+            max_len: None,
+            max_scan: None,
+            frecency: None,
+            sort: SortOrder::Score,
+            reverse: false,
+            tiers: false,
+            pinned: HashSet::new(),
+            cutoff_gap: None,
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Score,
+    Name,
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "score" => Ok(SortOrder::Score),
+            "name" => Ok(SortOrder::Name),
+            _ => Err(()),
+        }
+    }
+}
+
+// a selection count that halves every FRECENCY_HALF_LIFE_SECS since last_used (unix seconds)
+pub struct FrecencyEntry {
+    pub weight: f64,
+    pub last_used: u64,
+}
+
+pub const FRECENCY_HALF_LIFE_SECS: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+// score added per (decayed) selection, and at most
+const FRECENCY_BONUS_PER_SELECTION: f64 = 20.0;
+const FRECENCY_MAX_BONUS: f64 = 100.0;
+// entries that decayed below this are dropped when the store is saved
+const FRECENCY_MIN_WEIGHT: f64 = 0.05;
+
+#[derive(Default)]
+pub struct Frecency {
+    pub entries: HashMap<String, FrecencyEntry>,
+}
+
+impl FrecencyEntry {
+    pub fn decayed_weight(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_used) as f64;
+        self.weight * 0.5_f64.powf(age / FRECENCY_HALF_LIFE_SECS)
+    }
+}
+
+impl Frecency {
+    pub fn record(&mut self, text: &str, now: u64) {
+        let weight = self
+            .entries
+            .get(text)
+            .map_or(0.0, |entry| entry.decayed_weight(now));
+        self.entries.insert(
+            text.to_string(),
+            FrecencyEntry {
+                weight: weight + 1.0,
+                last_used: now,
+            },
+        );
+    }
+
+    // added to the score of the option, so a few recent selections can lift it past close matches
+    pub fn bonus(&self, text: &str, now: u64) -> usize {
+        self.entries.get(text).map_or(0, |entry| {
+            (entry.decayed_weight(now) * FRECENCY_BONUS_PER_SELECTION).min(FRECENCY_MAX_BONUS)
+                as usize
+        })
+    }
+
+    pub fn prune(&mut self, now: u64) {
+        self.entries
+            .retain(|_, entry| entry.decayed_weight(now) >= FRECENCY_MIN_WEIGHT);
+    }
+}
+
+// seconds since the unix epoch, 0 for clocks set before it
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+// the points each fuzzy_match pass gave, an exact match gets MAX_SCORE and nothing else
+#[derive(Default, Debug, PartialEq, Serialize)]
+pub struct ScoreBreakdown {
+    pub exact: usize,
+    pub substring: usize,
+    pub prefix: usize,
+    // the matched characters
+    pub subsequence: usize,
+    // the bonus for a subsequence without gaps, less for every skipped candidate byte
+    pub gaps: usize,
+    pub acronym: usize,
+    pub edit_distance: usize,
+}
+
+impl ScoreBreakdown {
+    // the score, clamped to MAX_SCORE like every scorer
+    pub fn total(&self) -> usize {
+        (self.exact
+            + self.substring
+            + self.prefix
+            + self.subsequence
+            + self.gaps
+            + self.acronym
+            + self.edit_distance)
+            .min(MAX_SCORE)
+    }
+}
+
+// switches for the individual fuzzy_match passes, all enabled by default
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ScoringConfig {
+    pub enable_substring: bool,
+    pub enable_prefix: bool,
+    pub enable_subsequence: bool,
+    pub enable_edit_distance: bool,
+    pub enable_acronym: bool,
+    // favor matches near the end of the candidate, the file name for paths
+    pub prefer_end: bool,
+    // how much less each later matched char counts in the substring and subsequence passes, 0
+    // counts every char the same
+    pub position_decay: f32,
+    // full Unicode case folding instead of to_lowercase, slower
+    pub case_folding: bool,
+    // match the slug forms of query and candidate, e.g. "hello-world" for "Hello, World!"
+    pub slug: bool,
+    // where words start for the acronym pass
+    pub tokenizer: Tokenizer,
+    // longest query in chars the edit distance pass runs for
+    pub edit_distance_max_query: usize,
 }
 
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            enable_substring: true,
+            enable_prefix: true,
+            enable_subsequence: true,
+            enable_edit_distance: true,
+            enable_acronym: true,
+            prefer_end: false,
+            position_decay: 0.0,
+            case_folding: false,
+            slug: false,
+            tokenizer: Tokenizer::Identifier,
+            edit_distance_max_query: 8,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FuzzyError {
+    // reading or writing a file failed
+    Io { path: String, source: io::Error },
+    // a filter pattern given on the command line is invalid
+    Pattern { pattern: String, message: String },
+    // the config file could not be parsed
+    Config { path: String, message: String },
+    // the embedding model could not be loaded or failed to embed
+    Model(String),
+    // drawing to or reading from the terminal failed
+    Terminal(io::Error),
+    // the embeddings file has no entry for a word that was asked for
+    MissingEmbedding { word: String, path: String },
+    // the model gives vectors of another length than the embeddings file has
+    DimensionMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for FuzzyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FuzzyError::Io { path, source } => write!(f, "{}: {}", path, source),
+            FuzzyError::Pattern { pattern, message } => {
+                write!(f, "invalid pattern '{}': {}", pattern, message)
+            }
+            FuzzyError::Config { path, message } => write!(f, "{}: {}", path, message),
+            FuzzyError::Model(message) => write!(f, "embedding model: {}", message),
+            FuzzyError::Terminal(source) => write!(f, "terminal: {}", source),
+            FuzzyError::MissingEmbedding { word, path } => {
+                write!(f, "{}: no embedding for '{}'", path, word)
+            }
+            FuzzyError::DimensionMismatch { expected, found } => write!(
+                f,
+                "the embeddings have {} dimensions but the model gives {}, rerun with --generate-embeddings",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FuzzyError {}
+
+// for the crossterm calls, file and stdout errors go through FuzzyError::io with their path
+impl From<io::Error> for FuzzyError {
+    fn from(source: io::Error) -> Self {
+        FuzzyError::Terminal(source)
+    }
+}
+
+impl FuzzyError {
+    pub fn io(path: &str, source: io::Error) -> Self {
+        FuzzyError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+}
+
+// how the suggestions are drawn
+pub struct DrawConfig {
+    // the score bar and value after each suggestion
+    pub show_bar: bool,
+    // a marker after suggestions that only matched semantically
+    pub mark_semantic: bool,
+    // the rank before each suggestion, right aligned
+    pub numbered: bool,
+    pub bar_scale: BarScale,
+    // longer labels are drawn as an ellipsis and their last characters, to keep file names visible
+    pub truncate_left: Option<usize>,
+}
+
+// what a full width score bar stands for
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BarScale {
+    // the distance from the lowest shown score, which gets no bar
+    #[default]
+    Spread,
+    // the share of the top score, which always gets the full width
+    Relative,
+    // the share of MAX_SCORE, comparable between queries
+    Absolute,
+}
+
+impl std::str::FromStr for BarScale {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "spread" => Ok(BarScale::Spread),
+            "relative" => Ok(BarScale::Relative),
+            "absolute" => Ok(BarScale::Absolute),
+            _ => Err(()),
+        }
+    }
+}
+
+// which ranking the interactive search uses, Ctrl+T switches between them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SearchMode {
+    Lexical,
+    Semantic,
+}
+
+impl SearchMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            SearchMode::Lexical => SearchMode::Semantic,
+            SearchMode::Semantic => SearchMode::Lexical,
+        }
+    }
+
+    // shown in the header
+    pub fn label(self) -> &'static str {
+        match self {
+            SearchMode::Lexical => "lexical",
+            SearchMode::Semantic => "semantic",
+        }
+    }
+}
+
+// Keeps the query a single line: lines are joined with a space, tabs become spaces and other
+// control characters are dropped.
+pub fn sanitize_query(text: &str) -> String {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .filter_map(|ch| match ch {
+            '\t' => Some(' '),
+            ch if ch.is_control() => None,
+            ch => Some(ch),
+        })
+        .collect()
+}
+
+// the query being typed, the cursor is a byte offset that always sits on a char boundary
+#[derive(Default)]
+pub struct QueryInput {
+    text: String,
+    cursor: usize,
+}
+
+impl QueryInput {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // the terminal column of the cursor relative to the query start, CJK and other wide chars
+    // take two
+    pub fn cursor_column(&self) -> usize {
+        self.text[..self.cursor].width()
+    }
+
+    fn previous_boundary(&self) -> Option<usize> {
+        self.text[..self.cursor]
+            .char_indices()
+            .next_back()
+            .map(|(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> Option<usize> {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map(|ch| self.cursor + ch.len_utf8())
+    }
+
+    pub fn insert(&mut self, ch: char) -> bool {
+        if ch.is_control() {
+            return false;
+        }
+        self.text.insert(self.cursor, ch);
+        self.cursor += ch.len_utf8();
+        true
+    }
+
+    // for pasted text, which can span lines or contain tabs
+    pub fn insert_str(&mut self, text: &str) -> bool {
+        let clean = sanitize_query(text);
+        self.text.insert_str(self.cursor, &clean);
+        self.cursor += clean.len();
+        !clean.is_empty()
+    }
+
+    // the editing operations return whether the text changed and has to be searched again
+    pub fn backspace(&mut self) -> bool {
+        let Some(start) = self.previous_boundary() else {
+            return false;
+        };
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+        true
+    }
+
+    pub fn delete(&mut self) -> bool {
+        let Some(end) = self.next_boundary() else {
+            return false;
+        };
+        self.text.replace_range(self.cursor..end, "");
+        true
+    }
+
+    // like readline, skips whitespace before the cursor and then removes the word before it
+    pub fn delete_word(&mut self) -> bool {
+        let before = self.text[..self.cursor].trim_end();
+        let start = before
+            .char_indices()
+            .rev()
+            .find(|(_, ch)| ch.is_whitespace())
+            .map_or(0, |(i, ch)| i + ch.len_utf8());
+        if start == self.cursor {
+            return false;
+        }
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+        true
+    }
+
+    pub fn clear(&mut self) -> bool {
+        if self.text.is_empty() {
+            return false;
+        }
+        self.text.clear();
+        self.cursor = 0;
+        true
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.previous_boundary().unwrap_or(self.cursor);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = self.next_boundary().unwrap_or(self.cursor);
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.text.len();
+    }
+}
+
+#[cfg(feature = "tui")]
 pub mod terminal_guard {
-    use crossterm::terminal;
+    use crossterm::{event, execute, terminal};
     use std::io;
 
     pub struct TerminalGuard;
@@ -13,13 +679,176 @@ pub mod terminal_guard {
     impl TerminalGuard {
         pub fn new() -> io::Result<Self> {
             terminal::enable_raw_mode()?;
+            // not supported by every terminal, pastes then arrive as single keys
+            let _ = execute!(io::stdout(), event::EnableBracketedPaste);
             Ok(Self)
         }
     }
 
     impl Drop for TerminalGuard {
         fn drop(&mut self) {
+            let _ = execute!(io::stdout(), event::DisableBracketedPaste);
             let _ = terminal::disable_raw_mode();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> QueryInput {
+        let mut query = QueryInput::default();
+        for ch in text.chars() {
+            query.insert(ch);
+        }
+        query
+    }
+
+    #[test]
+    fn insertions_happen_at_the_cursor() {
+        let mut query = typed("helo");
+        query.move_left();
+        query.insert('l');
+        assert_eq!((query.text(), query.cursor_column()), ("hello", 4));
+        query.move_home();
+        query.insert('>');
+        query.move_end();
+        query.insert('!');
+        assert_eq!((query.text(), query.cursor_column()), (">hello!", 7));
+    }
+
+    #[test]
+    fn delete_and_backspace_remove_around_the_cursor() {
+        let mut query = typed("abcd");
+        query.move_left();
+        query.move_left();
+        assert!(query.delete());
+        assert!(query.backspace());
+        assert_eq!((query.text(), query.cursor_column()), ("ad", 1));
+        query.move_end();
+        assert!(!query.delete());
+        query.move_home();
+        assert!(!query.backspace());
+        assert_eq!(query.text(), "ad");
+    }
+
+    #[test]
+    fn cursor_moves_over_whole_multibyte_chars() {
+        let mut query = typed("añb");
+        query.move_left();
+        query.move_left();
+        assert_eq!(query.cursor_column(), 1);
+        assert!(query.delete());
+        assert_eq!(query.text(), "ab");
+        // moving past either end stays put
+        query.move_right();
+        query.move_right();
+        assert_eq!(query.cursor_column(), 2);
+        query.move_home();
+        query.move_left();
+        assert_eq!(query.cursor_column(), 0);
+    }
+
+    #[test]
+    fn delete_word_removes_the_word_before_the_cursor() {
+        let mut query = typed("git commit  ");
+        assert!(query.delete_word());
+        assert_eq!(query.text(), "git ");
+        // only the part of the word before the cursor
+        let mut query = typed("src/main.rs");
+        query.move_left();
+        query.move_left();
+        query.move_left();
+        assert!(query.delete_word());
+        assert_eq!((query.text(), query.cursor_column()), (".rs", 0));
+        assert!(!query.delete_word());
+    }
+
+    #[test]
+    fn clear_empties_the_query() {
+        let mut query = typed("some query");
+        query.move_left();
+        assert!(query.clear());
+        assert_eq!((query.text(), query.cursor_column()), ("", 0));
+        assert!(!query.clear());
+    }
+
+    #[test]
+    fn pasted_text_is_sanitized_to_one_line() {
+        assert_eq!(
+            sanitize_query("  first line\r\n\nsecond\tline \n"),
+            "first line second line"
+        );
+        assert_eq!(sanitize_query("bell\u{7}\u{1b}[31m"), "bell[31m");
+        assert_eq!(sanitize_query("\n\t\n"), "");
+        let mut query = typed("ab");
+        query.move_left();
+        assert!(query.insert_str("x\ny"));
+        assert_eq!((query.text(), query.cursor_column()), ("ax yb", 4));
+        assert!(!query.insert('\n'));
+        assert!(!query.insert_str("\u{0}"));
+    }
+
+    #[test]
+    fn normalized_scores_are_pinned_per_kind_of_match() {
+        let config = ScoringConfig::default();
+        let normalized = |query, candidate| {
+            algorithms::fuzzy_match(query, candidate, &config)
+                .unwrap()
+                .normalized_score()
+        };
+        assert_eq!(normalized("hello", "hello"), 1.0);
+        // substring, prefix and subsequence
+        assert_eq!(normalized("hello", "hello, world"), 0.6);
+        // only the subsequence
+        assert_eq!(normalized("hlwrd", "hello world"), 0.094);
+        // clamped, whatever a scorer assembled
+        let sug = Suggestion {
+            score: MAX_SCORE * 3,
+            ..Default::default()
+        };
+        assert_eq!(sug.normalized_score(), 1.0);
+    }
+
+    #[test]
+    fn toggling_switches_the_mode_and_its_label() {
+        let mode = SearchMode::Lexical.toggled();
+        assert!(mode == SearchMode::Semantic);
+        assert_eq!(mode.label(), "semantic");
+        assert!(mode.toggled() == SearchMode::Lexical);
+        assert_eq!(mode.toggled().label(), "lexical");
+    }
+
+    #[test]
+    fn frecency_weight_halves_every_half_life() {
+        let week = FRECENCY_HALF_LIFE_SECS as u64;
+        let mut frecency = Frecency::default();
+        frecency.record("vim", 0);
+        frecency.record("vim", 0);
+        assert_eq!(frecency.bonus("vim", 0), 40);
+        assert_eq!(frecency.bonus("vim", week), 20);
+        assert_eq!(frecency.bonus("vim", 2 * week), 10);
+        assert_eq!(frecency.bonus("emacs", 0), 0);
+        // a new selection adds to what is left of the old ones
+        frecency.record("vim", week);
+        assert_eq!(frecency.entries["vim"].weight, 2.0);
+        // capped, however often it was picked
+        for _ in 0..20 {
+            frecency.record("vim", week);
+        }
+        assert_eq!(frecency.bonus("vim", week), 100);
+    }
+
+    #[test]
+    fn pruning_drops_entries_that_decayed_away() {
+        let week = FRECENCY_HALF_LIFE_SECS as u64;
+        let mut frecency = Frecency::default();
+        frecency.record("old", 0);
+        frecency.record("recent", 4 * week);
+        // a single selection falls below the minimum weight after five half lives
+        frecency.prune(5 * week);
+        assert!(frecency.entries.contains_key("recent"));
+        assert!(!frecency.entries.contains_key("old"));
+    }
+}